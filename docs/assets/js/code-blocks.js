@@ -0,0 +1,13 @@
+// Copy buttons on code blocks written with the `copy` attribute
+document.addEventListener('click', function (event) {
+  var button = event.target.closest('.code-copy');
+  if (!button || !navigator.clipboard) {
+    return;
+  }
+  navigator.clipboard.writeText(button.dataset.copy).then(function () {
+    button.textContent = 'Copied';
+    setTimeout(function () {
+      button.textContent = 'Copy';
+    }, 2000);
+  });
+});
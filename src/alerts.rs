@@ -0,0 +1,332 @@
+//! GitHub-style alerts: blockquotes starting with `[!NOTE]`, `[!TIP]`,
+//! `[!IMPORTANT]`, `[!WARNING]` or `[!CAUTION]` become `<aside>` boxes with
+//! an icon, a title and an ARIA role.
+//!
+//! ```md
+//! > [!WARNING]
+//! > `transfer` reverts when the fee rounds down to zero.
+//! ```
+//!
+//! Sites can restyle these or add their own, such as audit severities, under
+//! `[[alerts.levels]]` in `config.toml`:
+//!
+//! ```toml
+//! [[alerts.levels]]
+//! name = "high"          # written `> [!HIGH]`
+//! title = "High severity"
+//! icon = "alert-octagon"
+//! color = "#d93526"
+//! role = "alert"
+//! ```
+
+use pulldown_cmark::{BlockQuoteKind, CowStr, Event, Tag, TagEnd};
+
+use crate::escape::{html_attr, html_text};
+use crate::{AlertLevel, Alerts};
+
+type Error = Box<dyn std::error::Error>;
+
+/// How one kind of alert is rendered
+struct Style {
+    name: String,
+    title: String,
+    icon: &'static str,
+    color: Option<String>,
+    role: String,
+}
+
+/// Built-in kinds as (name, title, icon, role)
+const BUILT_IN: &[(&str, &str, &str, &str)] = &[
+    ("note", "Note", "info", "note"),
+    ("tip", "Tip", "zap", "note"),
+    ("important", "Important", "message-square", "note"),
+    ("warning", "Warning", "alert-triangle", "alert"),
+    ("caution", "Caution", "alert-octagon", "alert"),
+];
+
+/// Feather icons (https://feathericons.com) that alerts can use, as SVG contents
+const ICONS: &[(&str, &str)] = &[
+    (
+        "info",
+        r#"<circle cx="12" cy="12" r="10"></circle><line x1="12" y1="16" x2="12" y2="12"></line><line x1="12" y1="8" x2="12.01" y2="8"></line>"#,
+    ),
+    (
+        "zap",
+        r#"<polygon points="13 2 3 14 12 14 11 22 21 10 12 10 13 2"></polygon>"#,
+    ),
+    (
+        "message-square",
+        r#"<path d="M21 15a2 2 0 0 1-2 2H7l-4 4V5a2 2 0 0 1 2-2h14a2 2 0 0 1 2 2z"></path>"#,
+    ),
+    (
+        "alert-triangle",
+        r#"<path d="M10.29 3.86L1.82 18a2 2 0 0 0 1.71 3h16.94a2 2 0 0 0 1.71-3L13.71 3.86a2 2 0 0 0-3.42 0z"></path><line x1="12" y1="9" x2="12" y2="13"></line><line x1="12" y1="17" x2="12.01" y2="17"></line>"#,
+    ),
+    (
+        "alert-octagon",
+        r#"<polygon points="7.86 2 16.14 2 22 7.86 22 16.14 16.14 22 7.86 22 2 16.14 2 7.86 7.86 2"></polygon><line x1="12" y1="8" x2="12" y2="12"></line><line x1="12" y1="16" x2="12.01" y2="16"></line>"#,
+    ),
+    (
+        "alert-circle",
+        r#"<circle cx="12" cy="12" r="10"></circle><line x1="12" y1="8" x2="12" y2="12"></line><line x1="12" y1="16" x2="12.01" y2="16"></line>"#,
+    ),
+    (
+        "shield",
+        r#"<path d="M12 22s8-4 8-10V5l-8-3-8 3v7c0 6 8 10 8 10z"></path>"#,
+    ),
+    (
+        "check-circle",
+        r#"<path d="M22 11.08V12a10 10 0 1 1-5.93-9.08"></path><polyline points="22 4 12 14.01 9 11.01"></polyline>"#,
+    ),
+    (
+        "flag",
+        r#"<path d="M4 15s1-1 4-1 5 2 8 2 4-1 4-1V3s-1 1-4 1-5-2-8-2-4 1-4 1z"></path><line x1="4" y1="22" x2="4" y2="15"></line>"#,
+    ),
+];
+
+/// Replaces alert blockquotes in `events` with `<aside>` markup.
+pub fn transform<'a>(events: Vec<Event<'a>>, config: &Alerts) -> Result<Vec<Event<'a>>, Error> {
+    let styles = styles(config)?;
+    let mut output = Vec::with_capacity(events.len());
+    // One entry per open blockquote: whether it became an alert
+    let mut open: Vec<bool> = Vec::new();
+    let mut i = 0;
+
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::BlockQuote(kind)) => {
+                // pulldown-cmark recognises the five GitHub kinds itself;
+                // configured ones still start with a literal `[!NAME]` line
+                let (style, skip) = match kind {
+                    Some(kind) => (find(&styles, kind_name(*kind)), 0),
+                    None => match custom_marker(&events[i + 1..]) {
+                        Some((name, skip)) => (find(&styles, &name), skip),
+                        None => (None, 0),
+                    },
+                };
+
+                match style {
+                    Some(style) => {
+                        output.push(Event::Html(CowStr::from(open_tag(style))));
+                        open.push(true);
+                        i += 1;
+                        if skip > 0 {
+                            // Drop the marker, keeping the paragraph if text follows it
+                            let rest_of_paragraph = !matches!(
+                                events.get(i + skip),
+                                Some(Event::End(TagEnd::Paragraph))
+                            );
+                            if rest_of_paragraph {
+                                output.push(events[i].clone());
+                                i += skip;
+                            } else {
+                                i += skip + 1;
+                            }
+                        }
+                        continue;
+                    }
+                    None => {
+                        output.push(Event::Start(Tag::BlockQuote(None)));
+                        open.push(false);
+                    }
+                }
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                if open.pop().unwrap_or(false) {
+                    output.push(Event::Html(CowStr::from("</aside>\n")));
+                } else {
+                    output.push(Event::End(TagEnd::BlockQuote(None)));
+                }
+            }
+            event => output.push(event.clone()),
+        }
+        i += 1;
+    }
+
+    Ok(output)
+}
+
+/// Name of a `[!NAME]` marker opening a blockquote, and how many events
+/// after the blockquote start (the paragraph start, the marker text and the
+/// line break after it) it covers.
+fn custom_marker(events: &[Event]) -> Option<(String, usize)> {
+    if !matches!(events.first(), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+
+    let mut text = String::new();
+    let mut count = 1;
+    for event in &events[1..] {
+        match event {
+            Event::Text(t) => text.push_str(t),
+            Event::SoftBreak | Event::HardBreak => {
+                count += 1;
+                break;
+            }
+            Event::End(TagEnd::Paragraph) => break,
+            _ => return None,
+        }
+        count += 1;
+    }
+
+    let name = text.trim().strip_prefix("[!")?.strip_suffix(']')?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    Some((name.to_ascii_lowercase(), count))
+}
+
+fn kind_name(kind: BlockQuoteKind) -> &'static str {
+    match kind {
+        BlockQuoteKind::Note => "note",
+        BlockQuoteKind::Tip => "tip",
+        BlockQuoteKind::Important => "important",
+        BlockQuoteKind::Warning => "warning",
+        BlockQuoteKind::Caution => "caution",
+    }
+}
+
+fn find<'s>(styles: &'s [Style], name: &str) -> Option<&'s Style> {
+    styles.iter().find(|style| style.name == name)
+}
+
+/// Checks `[[alerts.levels]]` up front, so a bad entry stops the build before
+/// any page is rendered
+pub fn validate(config: &Alerts) -> Result<(), Error> {
+    styles(config).map(|_| ())
+}
+
+/// Built-in styles with the configured levels applied on top
+fn styles(config: &Alerts) -> Result<Vec<Style>, Error> {
+    let mut styles: Vec<Style> = BUILT_IN
+        .iter()
+        .map(|(name, title, icon, role)| Style {
+            name: name.to_string(),
+            title: title.to_string(),
+            icon: icon_svg(icon).expect("built-in icons exist"),
+            color: None,
+            role: role.to_string(),
+        })
+        .collect();
+
+    for level in &config.levels {
+        let name = level.name.to_ascii_lowercase();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid alert name '{}' in [[alerts.levels]]", level.name).into());
+        }
+        let existing = styles.iter().position(|style| style.name == name);
+        let style = match existing {
+            Some(index) => &mut styles[index],
+            None => {
+                styles.push(new_style(&name));
+                styles.last_mut().unwrap()
+            }
+        };
+        apply(style, level)?;
+    }
+    Ok(styles)
+}
+
+/// A configured level that isn't one of the built-in kinds
+fn new_style(name: &str) -> Style {
+    let mut title = name.replace(['-', '_'], " ");
+    if let Some(first) = title.get(..1) {
+        title = first.to_ascii_uppercase() + &title[1..];
+    }
+    Style {
+        name: name.to_string(),
+        title,
+        icon: icon_svg("info").expect("built-in icons exist"),
+        color: None,
+        role: "note".to_string(),
+    }
+}
+
+fn apply(style: &mut Style, level: &AlertLevel) -> Result<(), Error> {
+    if let Some(title) = &level.title {
+        style.title = title.clone();
+    }
+    if let Some(icon) = &level.icon {
+        style.icon = icon_svg(icon).ok_or_else(|| {
+            let names: Vec<&str> = ICONS.iter().map(|(name, _)| *name).collect();
+            format!(
+                "Unknown alert icon '{}' for '{}', expected one of: {}",
+                icon,
+                level.name,
+                names.join(", ")
+            )
+        })?;
+    }
+    if let Some(color) = &level.color {
+        // It ends up in a `style` attribute, so nothing but a colour gets in
+        if !is_color(color) {
+            return Err(format!(
+                "Invalid alert color '{}' for '{}', expected e.g. #d93526, red or rgb(217 53 38)",
+                color, level.name
+            )
+            .into());
+        }
+        style.color = Some(color.clone());
+    }
+    if let Some(role) = &level.role {
+        if !matches!(role.as_str(), "note" | "alert" | "status") {
+            return Err(format!(
+                "Unknown alert role '{}' for '{}', expected note, alert or status",
+                role, level.name
+            )
+            .into());
+        }
+        style.role = role.clone();
+    }
+    Ok(())
+}
+
+/// A hex colour (`#rgb`, `#rrggbb`, optionally with alpha), a named colour,
+/// or a colour function such as `rgb()` or `hsl()` of plain values
+fn is_color(value: &str) -> bool {
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit());
+    }
+    if let Some((function, args)) = value.strip_suffix(')').and_then(|v| v.split_once('(')) {
+        return matches!(
+            function,
+            "rgb" | "rgba" | "hsl" | "hsla" | "hwb" | "lab" | "lch" | "oklab" | "oklch"
+        ) && args
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b" .,%/+-".contains(&b));
+    }
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+fn icon_svg(name: &str) -> Option<&'static str> {
+    ICONS
+        .iter()
+        .find(|(icon, _)| *icon == name)
+        .map(|(_, svg)| *svg)
+}
+
+/// `<aside>` and title line opening an alert
+fn open_tag(style: &Style) -> String {
+    let color = style
+        .color
+        .as_ref()
+        .map(|color| format!(" style=\"--alert-color: {}\"", html_attr(color)))
+        .unwrap_or_default();
+    format!(
+        "<aside class=\"alert alert-{name}\" role=\"{role}\" aria-label=\"{label}\"{color}>\n<p class=\"alert-title\"><svg xmlns=\"http://www.w3.org/2000/svg\" width=\"18\" height=\"18\" viewBox=\"0 0 24 24\" fill=\"none\" stroke=\"currentColor\" stroke-width=\"2\" stroke-linecap=\"round\" stroke-linejoin=\"round\" class=\"alert-icon\" aria-hidden=\"true\" focusable=\"false\">{icon}</svg>{title}</p>\n",
+        name = style.name,
+        role = style.role,
+        label = html_attr(&style.title),
+        color = color,
+        icon = style.icon,
+        title = html_text(&style.title),
+    )
+}
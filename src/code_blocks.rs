@@ -0,0 +1,407 @@
+//! Code fence attributes, written after the language. Solidity fences take:
+//!
+//! ````md
+//! ```solidity diff copy anchors remix
+//! contract Vault {
+//! -    function withdraw() external { ... }
+//! +    function withdraw() external nonReentrant { ... }
+//! }
+//! ```
+//! ````
+//!
+//! - `diff`: lines starting with `-` are shown as vulnerable, `+` as fixed.
+//! - `copy`: a button copying the snippet (the fixed version in diff mode).
+//! - `anchors`: `contract X` and `function f` declarations get an `id`
+//!   (`sol-X`, `sol-X-f`) and a `#` link beside them.
+//! - `remix`: an "Open in Remix" link with the snippet encoded into the URL.
+//!
+//! Other words are skipped with a warning.
+//!
+//! Any fence can also take its contents from a file, relative to the content
+//! directory, optionally cut down to a 1-based inclusive line range:
+//!
+//! ````md
+//! ```solidity file="snippets/Token.sol" lines="10-42"
+//! ```
+//! ````
+//!
+//! Included files are reported back so the build can record them as
+//! dependencies of the page.
+//!
+//! Prism still highlights the code in the browser; line backgrounds and
+//! anchors sit in a layer positioned over the `<pre>`, which Prism leaves
+//! alone. Fences without attributes render as before.
+
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::escape::{html_attr, html_text};
+
+type Error = Box<dyn std::error::Error>;
+
+/// Remix IDE loads base64-encoded source from `#code=`
+const REMIX_URL: &str = "https://remix.ethereum.org/#code=";
+
+/// A code block that couldn't be filled in or rendered, which fails the build
+/// rather than just skipping the page
+#[derive(Debug)]
+pub struct BrokenCodeBlock(String);
+
+impl fmt::Display for BrokenCodeBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BrokenCodeBlock {}
+
+/// Attributes parsed from a fence's info string
+#[derive(Default)]
+struct Attributes {
+    file: Option<String>,
+    lines: Option<String>,
+    diff: bool,
+    copy: bool,
+    anchors: bool,
+    remix: bool,
+}
+
+/// How a line is marked in diff mode
+#[derive(Clone, Copy, PartialEq)]
+enum Change {
+    Context,
+    Removed,
+    Added,
+}
+
+/// Fills in `file=` blocks and renders Solidity fences that carry attributes
+/// in `events`, returning them with the files that were included.
+///
+/// `file=` paths are relative to `source_dir` and must stay inside it. A file
+/// that can't be read or a `lines=` range outside it is an error, which fails
+/// the build.
+pub fn transform<'a>(
+    events: Vec<Event<'a>>,
+    source_dir: &Path,
+) -> Result<(Vec<Event<'a>>, Vec<PathBuf>), BrokenCodeBlock> {
+    transform_blocks(events, source_dir).map_err(|e| BrokenCodeBlock(e.to_string()))
+}
+
+fn transform_blocks<'a>(
+    events: Vec<Event<'a>>,
+    source_dir: &Path,
+) -> Result<(Vec<Event<'a>>, Vec<PathBuf>), Error> {
+    let declaration = Regex::new(
+        r"^\s*(?:abstract\s+)?(contract|interface|library|function)\s+([A-Za-z_$][A-Za-z0-9_$]*)",
+    )?;
+    // Ids already given out on this page
+    let mut ids = HashSet::new();
+    let mut includes = Vec::new();
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let info = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => info.to_string(),
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+        let mut words = split_info(&info)?.into_iter();
+        let language = words.next().unwrap_or_default();
+        let solidity = matches!(language.as_str(), "solidity" | "sol");
+        // Other languages' info strings may carry words meant for other
+        // tools, so they're only touched when they include a file
+        let includes_file = words.clone().any(|word| word.starts_with("file="));
+        if words.len() == 0 || !(solidity || includes_file) {
+            output.push(event);
+            continue;
+        }
+        let attributes = parse_attributes(&language, words)?;
+
+        let mut source = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) => source.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => break,
+                _ => {}
+            }
+        }
+
+        if let Some(file) = &attributes.file {
+            if !source.trim().is_empty() {
+                return Err(format!(
+                    "Code block including '{}' must be empty, its contents come from the file",
+                    file
+                )
+                .into());
+            }
+            let path = content_path(source_dir, file)?;
+            source = include(&path, attributes.lines.as_deref())?;
+            if !includes.contains(&path) {
+                includes.push(path);
+            }
+        }
+
+        if attributes.diff || attributes.copy || attributes.anchors || attributes.remix {
+            let html = render(&source, &attributes, &declaration, &mut ids);
+            output.push(Event::Html(CowStr::from(html)));
+        } else {
+            // Only included: render like any other fence
+            output.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                CowStr::from(language),
+            ))));
+            output.push(Event::Text(CowStr::from(source)));
+            output.push(Event::End(TagEnd::CodeBlock));
+        }
+    }
+
+    Ok((output, includes))
+}
+
+/// Splits an info string on whitespace, keeping `key="quoted value"` whole
+/// (without the quotes)
+fn split_info(info: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in info.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("Unterminated quote in code block info string '{}'", info).into());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn parse_attributes(
+    language: &str,
+    words: impl Iterator<Item = String>,
+) -> Result<Attributes, Error> {
+    let solidity = matches!(language, "solidity" | "sol");
+    let mut attributes = Attributes::default();
+    for word in words {
+        match word.split_once('=') {
+            Some(("file", value)) => attributes.file = Some(value.to_string()),
+            Some(("lines", value)) => attributes.lines = Some(value.to_string()),
+            None if solidity && word == "diff" => attributes.diff = true,
+            None if solidity && word == "copy" => attributes.copy = true,
+            None if solidity && word == "anchors" => attributes.anchors = true,
+            None if solidity && word == "remix" => attributes.remix = true,
+            // Words such as `title=` or `linenos` may be meant for other tools
+            _ if solidity => println!(
+                "Ignoring unknown attribute '{}' on a Solidity code block, expected file=, lines=, diff, copy, anchors or remix",
+                word
+            ),
+            _ => {}
+        }
+    }
+    if attributes.lines.is_some() && attributes.file.is_none() {
+        return Err("lines= on a code block needs a file= to take them from".into());
+    }
+    Ok(attributes)
+}
+
+/// `file` joined onto `source_dir`, refusing absolute paths and `..` so posts
+/// can't pull in files from elsewhere on the machine
+fn content_path(source_dir: &Path, file: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(file);
+    let escapes = relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(format!(
+            "file=\"{}\" must be a path inside the content directory",
+            file
+        )
+        .into());
+    }
+    Ok(source_dir.join(relative))
+}
+
+/// Contents of `path`, or of the 1-based inclusive range `lines` ("10-42",
+/// "10-" or "10") of it
+fn include(path: &Path, lines: Option<&str>) -> Result<String, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not include {}: {}", path.display(), e))?;
+    let Some(range) = lines else {
+        return Ok(contents);
+    };
+
+    let all: Vec<&str> = contents.lines().collect();
+    let invalid = || format!("Invalid lines=\"{}\" for {}", range, path.display());
+    let out_of_range = || {
+        format!(
+            "lines=\"{}\" is out of range, {} has {} lines",
+            range,
+            path.display(),
+            all.len()
+        )
+    };
+    let parse = |n: &str| n.trim().parse::<usize>().map_err(|_| invalid());
+    let (first, last) = match range.split_once('-') {
+        Some((first, "")) => (parse(first)?, all.len()),
+        Some((first, last)) => (parse(first)?, parse(last)?),
+        None => (parse(range)?, parse(range)?),
+    };
+    // Checked before the order so "50-" on a 40 line file reads as out of
+    // range rather than backwards
+    if first > all.len() {
+        return Err(out_of_range().into());
+    }
+    if first == 0 || first > last {
+        return Err(invalid().into());
+    }
+    if last > all.len() {
+        return Err(out_of_range().into());
+    }
+
+    let mut selected = all[first - 1..last].join("\n");
+    selected.push('\n');
+    Ok(selected)
+}
+
+fn render(
+    source: &str,
+    attributes: &Attributes,
+    declaration: &Regex,
+    ids: &mut HashSet<String>,
+) -> String {
+    let lines: Vec<(Change, &str)> = source
+        .lines()
+        .map(|line| {
+            if !attributes.diff {
+                return (Change::Context, line);
+            }
+            match line.chars().next() {
+                Some('-') => (Change::Removed, &line[1..]),
+                Some('+') => (Change::Added, &line[1..]),
+                Some(' ') => (Change::Context, &line[1..]),
+                _ => (Change::Context, line),
+            }
+        })
+        .collect();
+
+    // What gets copied or opened elsewhere: the code as it ends up
+    let fixed: String = lines
+        .iter()
+        .filter(|(change, _)| *change != Change::Removed)
+        .map(|(_, line)| format!("{}\n", line))
+        .collect();
+
+    let mut overlay = String::new();
+    if attributes.diff {
+        for (number, (change, _)) in lines.iter().enumerate() {
+            // The `-`/`+` markers stay in the code for readers who can't
+            // see the colours
+            let class = match change {
+                Change::Context => continue,
+                Change::Removed => "diff-removed",
+                Change::Added => "diff-added",
+            };
+            overlay.push_str(&format!(
+                "<span class=\"code-line {}\" aria-hidden=\"true\" style=\"--line: {}\"></span>",
+                class, number
+            ));
+        }
+    }
+    if attributes.anchors {
+        let mut contract: Option<&str> = None;
+        for (number, (change, line)) in lines.iter().enumerate() {
+            // Link to the fixed declaration rather than both versions
+            if *change == Change::Removed {
+                continue;
+            }
+            let Some(caps) = declaration.captures(line) else {
+                continue;
+            };
+            let kind = caps.get(1).map_or("", |m| m.as_str());
+            let name = caps.get(2).map_or("", |m| m.as_str());
+            let (id, label) = if kind == "function" {
+                match contract {
+                    Some(contract) => (
+                        format!("sol-{}-{}", contract, name),
+                        format!("{}.{}", contract, name),
+                    ),
+                    None => (format!("sol-{}", name), name.to_string()),
+                }
+            } else {
+                contract = Some(name);
+                (format!("sol-{}", name), name.to_string())
+            };
+            let id = unique_id(id, ids);
+            overlay.push_str(&format!(
+                "<a id=\"{id}\" class=\"code-anchor\" href=\"#{id}\" aria-label=\"Link to {kind} {label}\" style=\"--line: {number}\">#</a>",
+                id = html_attr(&id),
+                kind = kind,
+                label = html_attr(&label),
+                number = number,
+            ));
+        }
+    }
+
+    let mut actions = String::new();
+    if attributes.copy {
+        actions.push_str(&format!(
+            "<button type=\"button\" class=\"code-copy\" data-copy=\"{}\">Copy</button>",
+            html_attr(&fixed)
+        ));
+    }
+    if attributes.remix {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(&fixed);
+        actions.push_str(&format!(
+            "<a class=\"code-remix\" href=\"{}{}\" target=\"_blank\" rel=\"noopener noreferrer\">Open in Remix</a>",
+            REMIX_URL,
+            utf8_percent_encode(&encoded, NON_ALPHANUMERIC)
+        ));
+    }
+
+    let mut html = String::from("<div class=\"code-block\">");
+    if !actions.is_empty() {
+        html.push_str(&format!("<div class=\"code-actions\">{}</div>", actions));
+    }
+    let pre_class = if attributes.diff {
+        "line-numbers code-diff"
+    } else {
+        "line-numbers"
+    };
+    html.push_str(&format!("<pre class=\"{}\">", pre_class));
+    if !overlay.is_empty() {
+        html.push_str(&format!("<span class=\"code-overlay\">{}</span>", overlay));
+    }
+    html.push_str(&format!(
+        "<code class=\"language-solidity\">{}</code></pre></div>\n",
+        html_text(source)
+    ));
+    html
+}
+
+/// `id`, or `id-2`, `id-3`, … if it's already used on the page
+fn unique_id(id: String, ids: &mut HashSet<String>) -> String {
+    let mut candidate = id.clone();
+    let mut n = 2;
+    while ids.contains(&candidate) {
+        candidate = format!("{}-{}", id, n);
+        n += 1;
+    }
+    ids.insert(candidate.clone());
+    candidate
+}
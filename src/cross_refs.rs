@@ -0,0 +1,111 @@
+//! Links between content files by source name:
+//!
+//! ```md
+//! See [the fee logic](@/build-deflationary-erc20.md#taking-the-fee).
+//! ```
+//!
+//! `@/` paths are relative to the content directory and resolve to wherever
+//! that post or page is built, so links survive title (and slug) changes.
+//! `#anchors` are checked against the `id`s on the target page once every page
+//! has been rendered. Any broken reference fails the build before anything is
+//! written to the output directory.
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::canonical_path;
+
+/// A reference to an anchor, checked after rendering
+struct Anchored {
+    /// Markdown file holding the link
+    from: String,
+    reference: String,
+    target_url: String,
+    anchor: String,
+}
+
+/// Cross-references seen during a build
+#[derive(Default)]
+pub struct CrossRefs {
+    /// `id`s on each rendered page, by site-relative URL
+    anchors: Mutex<HashMap<String, HashSet<String>>>,
+    pending: Mutex<Vec<Anchored>>,
+    errors: Mutex<Vec<String>>,
+}
+
+impl CrossRefs {
+    /// Site-relative URL (with any `#anchor`) for the `@/` reference
+    /// `reference` made in `from`, or `None` (recorded as an error) if it
+    /// doesn't name a post or page in this build.
+    pub fn resolve(
+        &self,
+        reference: &str,
+        from: &str,
+        source_dir: &Path,
+        content_urls: &HashMap<PathBuf, String>,
+    ) -> Option<String> {
+        let path = reference.strip_prefix("@/")?;
+        let (path, anchor) = match path.split_once('#') {
+            Some((path, anchor)) => (path, Some(anchor)),
+            None => (path, None),
+        };
+
+        let Some(target_url) = content_urls.get(&canonical_path(source_dir.join(path))) else {
+            self.error(format!(
+                "{}: {} doesn't match a post or page in {} (drafts are not built)",
+                from,
+                reference,
+                source_dir.display()
+            ));
+            return None;
+        };
+
+        match anchor {
+            Some(anchor) if !anchor.is_empty() => {
+                self.pending.lock().unwrap().push(Anchored {
+                    from: from.to_string(),
+                    reference: reference.to_string(),
+                    target_url: target_url.clone(),
+                    anchor: anchor.to_string(),
+                });
+                Some(format!("{}#{}", target_url, anchor))
+            }
+            _ => Some(target_url.clone()),
+        }
+    }
+
+    /// Notes the `id`s in a rendered page's content
+    pub fn record_anchors(&self, url: &str, html: &str) {
+        let id = Regex::new(r#"\sid="([^"]+)""#).expect("valid regex");
+        let ids = id
+            .captures_iter(html)
+            .map(|caps| caps[1].to_string())
+            .collect();
+        self.anchors.lock().unwrap().insert(url.to_string(), ids);
+    }
+
+    /// Every broken reference, once all pages are rendered
+    pub fn errors(&self) -> Vec<String> {
+        let anchors = self.anchors.lock().unwrap();
+        let mut errors = self.errors.lock().unwrap().clone();
+        for pending in self.pending.lock().unwrap().iter() {
+            let found = anchors
+                .get(&pending.target_url)
+                .is_some_and(|ids| ids.contains(&pending.anchor));
+            if !found {
+                errors.push(format!(
+                    "{}: {} points at #{}, which isn't an anchor on {}",
+                    pending.from, pending.reference, pending.anchor, pending.target_url
+                ));
+            }
+        }
+        errors.sort();
+        errors
+    }
+
+    fn error(&self, message: String) {
+        self.errors.lock().unwrap().push(message);
+    }
+}
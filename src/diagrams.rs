@@ -0,0 +1,212 @@
+//! Diagrams written as fenced code blocks.
+//!
+//! ```` ```dot ```` blocks (Graphviz) are laid out at build time and inlined
+//! as SVG. There is no pure-Rust Mermaid renderer, so ```` ```mermaid ````
+//! blocks are left to mermaid.js in the browser: the source goes into a
+//! `<pre class="mermaid">` container, which still reads as text without
+//! JavaScript.
+//!
+//! Rendered SVG is cached in `.diagram-cache/`, keyed by a hash of the block,
+//! so unchanged diagrams aren't laid out again on the next build.
+
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::escape::html_text;
+
+/// Loads mermaid.js once per page that has a Mermaid diagram
+const MERMAID_SCRIPT: &str = "<script type=\"module\">import mermaid from 'https://cdn.jsdelivr.net/npm/mermaid@11/dist/mermaid.esm.min.mjs'; mermaid.initialize({ startOnLoad: true });</script>\n";
+
+/// Bumped when the SVG produced for the same source changes, so stale cache
+/// entries are ignored
+const RENDERER_VERSION: &str = "layout-rs-0.1";
+
+/// Replaces ```` ```dot ```` and ```` ```mermaid ```` blocks in `events`,
+/// caching rendered SVG beneath `root_dir`.
+pub fn transform<'a>(events: Vec<Event<'a>>, root_dir: &Path) -> Vec<Event<'a>> {
+    let cache = Cache {
+        dir: root_dir.join(".diagram-cache"),
+    };
+    let mut output = Vec::with_capacity(events.len());
+    let mut has_mermaid = false;
+    // Dot diagrams so far on this page, to keep their ids apart
+    let mut dot_count = 0;
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let kind = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                match info.split_whitespace().next() {
+                    Some("dot") => Kind::Dot,
+                    Some("mermaid") => Kind::Mermaid,
+                    _ => {
+                        output.push(event);
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        // The block's text arrives as one or more Text events
+        let mut source = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) => source.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => break,
+                _ => {}
+            }
+        }
+
+        let html = match kind {
+            Kind::Dot => {
+                dot_count += 1;
+                render_dot(&source, dot_count, &cache)
+            }
+            Kind::Mermaid => {
+                has_mermaid = true;
+                format!(
+                    "<div class=\"diagram diagram-mermaid\"><pre class=\"mermaid\">{}</pre></div>\n",
+                    html_text(&source)
+                )
+            }
+        };
+        output.push(Event::Html(CowStr::from(html)));
+    }
+
+    if has_mermaid {
+        output.push(Event::Html(CowStr::from(MERMAID_SCRIPT)));
+    }
+    output
+}
+
+enum Kind {
+    Dot,
+    Mermaid,
+}
+
+/// Inline SVG for a Graphviz block, or its source if it can't be laid out
+///
+/// `index` numbers the diagram on its page; with the content hash it prefixes
+/// the SVG's ids, so even the same diagram twice on a page doesn't clash.
+fn render_dot(source: &str, index: usize, cache: &Cache) -> String {
+    let key = cache.key("dot", source);
+    let svg = match cache.get(&key) {
+        Some(svg) => Ok(svg),
+        None => dot_to_svg(source).inspect(|svg| cache.put(&key, svg)),
+    };
+
+    match svg {
+        Ok(svg) => format!(
+            "<figure class=\"diagram diagram-dot\">{}</figure>\n",
+            scope_ids(strip_prolog(&svg), &format!("dot-{}-{}", &key[..12], index))
+        ),
+        Err(e) => {
+            println!("Could not render dot diagram: {}, showing its source", e);
+            format!(
+                "<pre class=\"diagram-error\"><code class=\"language-dot\">{}</code></pre>\n",
+                html_text(source)
+            )
+        }
+    }
+}
+
+fn dot_to_svg(source: &str) -> Result<String, String> {
+    let mut parser = DotParser::new(source);
+    let graph = parser.process()?;
+
+    // The layout code asserts on graphs it can't handle rather than
+    // returning an error; don't let one diagram take the build down
+    let result = panic::catch_unwind(|| {
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual = builder.get();
+        let mut writer = SVGWriter::new();
+        visual.do_it(false, false, false, &mut writer);
+        writer.finalize()
+    });
+
+    result.map_err(|_| "the layout engine failed on this graph".to_string())
+}
+
+/// Drops the XML declaration so the SVG can sit inside HTML
+fn strip_prolog(svg: &str) -> &str {
+    match svg.find("<svg") {
+        Some(start) => svg[start..].trim_end(),
+        None => svg.trim_end(),
+    }
+}
+
+/// Prefixes the ids and classes layout-rs always emits (`endarrow`, `arrow0`,
+/// `a14`, ...) and every reference to them, so diagrams sharing a page don't
+/// pick up each other's markers, label paths and styles
+fn scope_ids(svg: &str, prefix: &str) -> String {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    static CLASS: OnceLock<Regex> = OnceLock::new();
+    static STYLE: OnceLock<Regex> = OnceLock::new();
+    static SELECTOR: OnceLock<Regex> = OnceLock::new();
+    let reference = REFERENCE.get_or_init(|| Regex::new(r##"(\sid="|url\(#|href="#)"##).unwrap());
+    let class = CLASS.get_or_init(|| Regex::new(r#"\sclass="([^"]*)""#).unwrap());
+    let style = STYLE.get_or_init(|| Regex::new(r"(?s)<style>(.*?)</style>").unwrap());
+    let selector = SELECTOR.get_or_init(|| Regex::new(r"\.([A-Za-z_][\w-]*)").unwrap());
+
+    let svg = reference.replace_all(svg, format!("${{1}}{}-", prefix));
+    let svg = class.replace_all(&svg, |caps: &Captures| {
+        let classes: Vec<String> = caps[1]
+            .split_whitespace()
+            .map(|name| format!("{}-{}", prefix, name))
+            .collect();
+        format!(" class=\"{}\"", classes.join(" "))
+    });
+    style
+        .replace_all(&svg, |caps: &Captures| {
+            format!(
+                "<style>{}</style>",
+                selector.replace_all(&caps[1], format!(".{}-$1", prefix))
+            )
+        })
+        .into_owned()
+}
+
+/// Rendered diagrams on disk, one file per content hash
+struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    fn key(&self, kind: &str, source: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(RENDERER_VERSION);
+        hasher.update([0]);
+        hasher.update(kind);
+        hasher.update([0]);
+        hasher.update(source);
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.dir.join(format!("{}.svg", key))).ok()
+    }
+
+    /// Failing to write the cache only costs a re-render next time
+    fn put(&self, key: &str, svg: &str) {
+        let path = self.dir.join(format!("{}.svg", key));
+        if let Err(e) = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&path, svg)) {
+            println!("Could not cache diagram {}: {}", path.display(), e);
+        }
+    }
+}
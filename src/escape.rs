@@ -0,0 +1,102 @@
+//! Context-aware escaping for values interpolated into generated markup.
+//!
+//! Front matter and `config.toml` values are user content. Every generator
+//! runs them through the helper matching where they end up in the output, so
+//! a stray apostrophe or `<` can never break (or inject) markup.
+
+/// Escapes text placed between tags, e.g. inside `<title>`, `<h1>` or `<a>`.
+///
+/// Also safe for XML element content such as `<loc>` in `sitemap.xml`.
+pub fn html_text(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a value placed inside a quoted attribute.
+///
+/// Both quote styles are escaped because the template uses single quotes
+/// while pulldown-cmark output uses double quotes.
+pub fn html_attr(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a URL placed inside an `href`/`src`/`content` attribute.
+///
+/// Script-capable schemes (`javascript:`, `vbscript:`, `data:`) are replaced
+/// with `#`, whitespace and control characters are percent-encoded, and the
+/// result is attribute-escaped.
+pub fn url_attr(input: &str) -> String {
+    let trimmed = input.trim();
+    let scheme = trimmed
+        .split(':')
+        .next()
+        .filter(|_| trimmed.contains(':'))
+        .map(|s| {
+            s.chars()
+                .filter(|c| !c.is_whitespace() && !c.is_control())
+                .collect::<String>()
+                .to_ascii_lowercase()
+        });
+    if let Some(scheme) = scheme {
+        if matches!(scheme.as_str(), "javascript" | "vbscript" | "data") {
+            return "#".to_string();
+        }
+    }
+
+    let mut encoded = String::with_capacity(trimmed.len());
+    for c in trimmed.chars() {
+        if c.is_whitespace() || c.is_control() {
+            let mut buf = [0u8; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    html_attr(&encoded)
+}
+
+/// Escapes a value for use inside a JSON string literal (without the quotes).
+///
+/// `<`, `>` and `&` are emitted as `\u` escapes so the result can sit inside a
+/// `<script>` block without closing it early.
+pub fn json_string(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '<' => out.push_str("\\u003c"),
+            '>' => out.push_str("\\u003e"),
+            '&' => out.push_str("\\u0026"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => out.push(c),
+        }
+    }
+    out
+}
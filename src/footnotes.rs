@@ -0,0 +1,259 @@
+//! Footnotes: `[^label]` references become numbered superscripts linking to
+//! a list at the end of the content, and every definition links back to each
+//! place it was referenced.
+//!
+//! Numbers follow the order footnotes are first referenced. A footnote used
+//! several times keeps one number and gets numbered back-links (↩¹, ↩², …).
+//! Definitions that are never referenced are dropped, and references to
+//! missing definitions are left as text, both with a warning.
+//!
+//! With `sidenotes = true` under `[footnotes]` in `config.toml`, notes whose
+//! body is plain text are also repeated beside the reference, shown in the
+//! margin on wide screens. The list at the end stays for narrow screens and
+//! screen readers.
+
+use pulldown_cmark::{html, CowStr, Event, Tag, TagEnd};
+use std::collections::HashMap;
+
+use crate::Footnotes;
+
+/// One footnote in reference order
+struct Note<'a> {
+    label: String,
+    /// How many times it has been referenced so far
+    references: usize,
+    body: Vec<Event<'a>>,
+}
+
+/// Replaces footnote references and definitions in `events`, appending the
+/// numbered list after the content.
+pub fn transform<'a>(events: Vec<Event<'a>>, config: &Footnotes) -> Vec<Event<'a>> {
+    let (events, mut definitions) = take_definitions(events);
+    if definitions.is_empty() && !events.iter().any(is_reference) {
+        return events;
+    }
+
+    let mut notes: Vec<Note<'a>> = Vec::new();
+    let mut output = replace_references(events, &mut notes, &definitions, config.sidenotes);
+
+    // Definitions can reference other footnotes, which get numbers as
+    // they're reached, so the list can grow while it's being built
+    let mut items = Vec::new();
+    let mut index = 0;
+    while index < notes.len() {
+        let body = std::mem::take(&mut notes[index].body);
+        let body = replace_references(body, &mut notes, &definitions, false);
+        items.push(body);
+        index += 1;
+    }
+
+    for note in &notes {
+        definitions.remove(&note.label);
+    }
+    let mut unused: Vec<&String> = definitions.keys().collect();
+    unused.sort();
+    for label in unused {
+        println!("Footnote [^{}] is never referenced, dropping it", label);
+    }
+
+    if notes.is_empty() {
+        return output;
+    }
+
+    output.push(Event::Html(CowStr::from(format!(
+        "<section class=\"footnotes{}\" role=\"doc-endnotes\" aria-labelledby=\"footnotes-label\">\n<h2 id=\"footnotes-label\" class=\"footnotes-title\">Footnotes</h2>\n<ol>\n",
+        if config.sidenotes { " footnotes-with-sidenotes" } else { "" }
+    ))));
+    for (i, (note, body)) in notes.iter().zip(items).enumerate() {
+        let number = i + 1;
+        output.push(Event::Html(CowStr::from(format!(
+            "<li id=\"fn-{}\">\n",
+            number
+        ))));
+        output.extend(with_back_links(body, number, note.references));
+        output.push(Event::Html(CowStr::from("</li>\n")));
+    }
+    output.push(Event::Html(CowStr::from("</ol>\n</section>\n")));
+    output
+}
+
+fn is_reference(event: &Event) -> bool {
+    matches!(event, Event::FootnoteReference(_))
+}
+
+/// Footnote labels match case-insensitively, as link labels do
+fn normalize(label: &str) -> String {
+    label.trim().to_lowercase()
+}
+
+/// Splits footnote definitions out of `events`, keyed by normalized label
+fn take_definitions(events: Vec<Event>) -> (Vec<Event>, HashMap<String, Vec<Event>>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut definitions: HashMap<String, Vec<Event>> = HashMap::new();
+    let mut current: Option<(String, Vec<Event>)> = None;
+    let mut depth = 0;
+
+    for event in events {
+        match (&event, current.as_mut()) {
+            (Event::Start(Tag::FootnoteDefinition(label)), None) => {
+                current = Some((normalize(label), Vec::new()));
+            }
+            (Event::Start(Tag::FootnoteDefinition(_)), Some((_, body))) => {
+                depth += 1;
+                body.push(event);
+            }
+            (Event::End(TagEnd::FootnoteDefinition), Some((_, body))) => {
+                if depth == 0 {
+                    let (label, body) = current.take().expect("inside a definition");
+                    // Like link definitions, the first one wins
+                    definitions.entry(label).or_insert(body);
+                } else {
+                    depth -= 1;
+                    body.push(event);
+                }
+            }
+            (_, Some((_, body))) => body.push(event),
+            (_, None) => output.push(event),
+        }
+    }
+
+    (output, definitions)
+}
+
+/// Numbers the references in `events`, registering new footnotes in `notes`
+/// and adding sidenotes after them if asked to
+fn replace_references<'a>(
+    events: Vec<Event<'a>>,
+    notes: &mut Vec<Note<'a>>,
+    definitions: &HashMap<String, Vec<Event<'a>>>,
+    sidenotes: bool,
+) -> Vec<Event<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+
+    for event in events {
+        let Event::FootnoteReference(label) = &event else {
+            output.push(event);
+            continue;
+        };
+        let key = normalize(label);
+        let Some(definition) = definitions.get(&key) else {
+            println!(
+                "Footnote [^{}] has no definition, leaving it as text",
+                label
+            );
+            output.push(Event::Text(CowStr::from(format!("[^{}]", label))));
+            continue;
+        };
+
+        let index = match notes.iter().position(|note| note.label == key) {
+            Some(index) => index,
+            None => {
+                notes.push(Note {
+                    label: key,
+                    references: 0,
+                    body: definition.clone(),
+                });
+                notes.len() - 1
+            }
+        };
+        notes[index].references += 1;
+        let number = index + 1;
+        let occurrence = notes[index].references;
+
+        let mut reference = format!(
+            "<sup class=\"footnote-ref\"><a href=\"#fn-{number}\" id=\"{id}\" role=\"doc-noteref\" aria-describedby=\"footnotes-label\">{number}</a></sup>",
+            number = number,
+            id = reference_id(number, occurrence),
+        );
+        if sidenotes {
+            if let Some(text) = sidenote_text(definition) {
+                reference.push_str(&format!(
+                    "<span class=\"sidenote\" aria-hidden=\"true\"><span class=\"sidenote-number\">{}</span> {}</span>",
+                    number, text
+                ));
+            }
+        }
+        output.push(Event::InlineHtml(CowStr::from(reference)));
+    }
+
+    output
+}
+
+/// `id` of the n-th reference to a footnote; the first keeps the short form
+fn reference_id(number: usize, occurrence: usize) -> String {
+    if occurrence == 1 {
+        format!("fnref-{}", number)
+    } else {
+        format!("fnref-{}-{}", number, occurrence)
+    }
+}
+
+/// Inline HTML for a definition made only of paragraphs of text, which can
+/// sit inside the paragraph holding the reference
+fn sidenote_text(definition: &[Event]) -> Option<String> {
+    let mut inline = Vec::new();
+    let mut paragraphs = 0;
+
+    for event in definition {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                if paragraphs > 0 {
+                    inline.push(Event::Html(CowStr::from("<br>")));
+                }
+                paragraphs += 1;
+            }
+            Event::End(TagEnd::Paragraph) => {}
+            // Nested notes would need numbers of their own
+            Event::FootnoteReference(_) => return None,
+            // Block markup, such as an image laid out as a figure
+            Event::Html(_) => return None,
+            Event::Start(tag) if !is_inline(tag) => return None,
+            event => inline.push(event.clone()),
+        }
+    }
+
+    let mut text = String::new();
+    html::push_html(&mut text, inline.into_iter());
+    Some(text)
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. } | Tag::Image { .. }
+    )
+}
+
+/// Appends links back to every reference, inside the last paragraph when the
+/// definition ends with one
+fn with_back_links(mut body: Vec<Event>, number: usize, references: usize) -> Vec<Event> {
+    let links: Vec<String> = (1..=references)
+        .map(|occurrence| {
+            let (label, mark) = if references == 1 {
+                (format!("Back to reference {}", number), String::new())
+            } else {
+                (
+                    format!("Back to reference {} ({})", number, occurrence),
+                    format!("<sup>{}</sup>", occurrence),
+                )
+            };
+            format!(
+                "<a href=\"#{}\" class=\"footnote-backref\" role=\"doc-backlink\" aria-label=\"{}\">↩{}</a>",
+                reference_id(number, occurrence),
+                label,
+                mark
+            )
+        })
+        .collect();
+    let links = format!(" {}", links.join(" "));
+
+    if matches!(body.last(), Some(Event::End(TagEnd::Paragraph))) {
+        body.insert(body.len() - 1, Event::InlineHtml(CowStr::from(links)));
+    } else {
+        body.push(Event::Html(CowStr::from(format!(
+            "<p>{}</p>\n",
+            links.trim_start()
+        ))));
+    }
+    body
+}
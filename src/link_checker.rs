@@ -0,0 +1,412 @@
+//! `check-links`: verifies links in the generated site.
+//!
+//! Every `href`/`src` in `docs/**/*.html` is resolved against the output
+//! directory; `#fragment`s must match an `id` (or `<a name>`) in the target
+//! page. External URLs are only fetched when asked for, in parallel, with
+//! successes cached on disk and an allowlist of prefixes to skip.
+
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Options for a `check-links` run.
+pub struct CheckOptions {
+    /// Output directory holding the generated site (`docs`).
+    pub root: PathBuf,
+    /// `base_url` from `config.toml`; absolute links under it are checked locally.
+    pub base_url: String,
+    /// Fetch external URLs as well.
+    pub external: bool,
+    /// Number of external URLs fetched at the same time.
+    pub concurrency: usize,
+    /// File of URL prefixes that are never fetched, one per line.
+    pub allowlist: PathBuf,
+    /// File recording external URLs that were reachable, with a timestamp.
+    pub cache: PathBuf,
+    /// How long a cached success is trusted.
+    pub cache_ttl: Duration,
+}
+
+/// A link that failed to resolve.
+struct BrokenLink {
+    page: PathBuf,
+    link: String,
+    reason: String,
+}
+
+/// Checks every link in the site and prints a report.
+///
+/// Returns the number of broken links.
+pub fn check_links(options: &CheckOptions) -> std::io::Result<usize> {
+    let mut pages = Vec::new();
+    collect_html_files(&options.root, &mut pages)?;
+    pages.sort();
+
+    let link_re = Regex::new(r#"\b(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    // Any element's `id` is a fragment target, but `name` only on `<a>`
+    let id_re = Regex::new(r#"<[^>]*\sid\s*=\s*(?:"([^"]*)"|'([^']*)')[^>]*>"#).unwrap();
+    let name_re = Regex::new(r#"<a\b[^>]*\sname\s*=\s*(?:"([^"]*)"|'([^']*)')[^>]*>"#).unwrap();
+
+    // Read every page once, remembering its ids for fragment checks
+    let mut sources: Vec<(PathBuf, String)> = Vec::new();
+    let mut ids: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    for page in &pages {
+        let html = fs::read_to_string(page)?;
+        let page_ids = id_re
+            .captures_iter(&html)
+            .chain(name_re.captures_iter(&html))
+            .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+            .map(|m| decode_entities(m.as_str()))
+            .collect();
+        ids.insert(normalize(page), page_ids);
+        sources.push((page.clone(), html));
+    }
+
+    let mut broken = Vec::new();
+    let mut external_links: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut internal_count = 0;
+
+    for (page, html) in &sources {
+        for caps in link_re.captures_iter(html) {
+            let raw = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+            let link = decode_entities(raw.trim());
+
+            if link.is_empty() || is_ignored_scheme(&link) {
+                continue;
+            }
+
+            let local = strip_base_url(&link, &options.base_url);
+            if local.is_none() && is_external(&link) {
+                external_links
+                    .entry(link.clone())
+                    .or_default()
+                    .push(page.clone());
+                continue;
+            }
+
+            internal_count += 1;
+            let target = local.unwrap_or(&link);
+            if let Err(reason) = check_internal(page, target, &options.root, &ids) {
+                broken.push(BrokenLink {
+                    page: page.clone(),
+                    link: link.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    println!(
+        "Checked {} internal links across {} pages",
+        internal_count,
+        pages.len()
+    );
+
+    if options.external {
+        broken.extend(check_external(&external_links, options));
+    } else if !external_links.is_empty() {
+        println!(
+            "Skipped {} external URLs (pass --external to check them)",
+            external_links.len()
+        );
+    }
+
+    // The same link is often repeated on a page (header and footer); report it once
+    broken.sort_by(|a, b| a.page.cmp(&b.page).then(a.link.cmp(&b.link)));
+    broken.dedup_by(|a, b| a.page == b.page && a.link == b.link);
+    for link in &broken {
+        println!(
+            "{}: broken link '{}' ({})",
+            link.page.display(),
+            link.link,
+            link.reason
+        );
+    }
+
+    if broken.is_empty() {
+        println!("No broken links found");
+    } else {
+        println!("Found {} broken links", broken.len());
+    }
+
+    Ok(broken.len())
+}
+
+/// Recursively lists `.html` files under `dir`.
+fn collect_html_files(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_html_files(&path, out)?;
+        } else if path.extension().and_then(|e| e.to_str()) == Some("html") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Undoes the entity escaping applied to attribute values.
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Links that don't point at a document: mail, phone, inline script and data.
+fn is_ignored_scheme(link: &str) -> bool {
+    let lower = link.to_ascii_lowercase();
+    ["mailto:", "tel:", "javascript:", "data:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+}
+
+fn is_external(link: &str) -> bool {
+    let lower = link.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//")
+}
+
+/// Turns an absolute URL on our own site into a root-relative path.
+fn strip_base_url<'a>(link: &'a str, base_url: &str) -> Option<&'a str> {
+    let base = base_url.trim_end_matches('/');
+    if base.is_empty() || link.len() < base.len() {
+        return None;
+    }
+    let (head, rest) = link.split_at(base.len());
+    if !head.eq_ignore_ascii_case(base) {
+        return None;
+    }
+    if rest.is_empty() {
+        Some("/")
+    } else if rest.starts_with(['/', '#', '?']) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Checks that an internal link resolves to a file (and anchor) under `root`.
+fn check_internal(
+    page: &Path,
+    link: &str,
+    root: &Path,
+    ids: &HashMap<PathBuf, HashSet<String>>,
+) -> Result<(), String> {
+    let (without_fragment, fragment) = match link.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (link, None),
+    };
+    let path_part = without_fragment
+        .split_once('?')
+        .map_or(without_fragment, |(path, _)| path);
+    let decoded = percent_decode_str(path_part).decode_utf8_lossy();
+
+    let mut target = if decoded.is_empty() {
+        page.to_path_buf()
+    } else if let Some(absolute) = decoded.strip_prefix('/') {
+        root.join(absolute)
+    } else {
+        page.parent().unwrap_or(root).join(decoded.as_ref())
+    };
+    target = normalize(&target);
+
+    if !target.starts_with(normalize(root)) {
+        return Err("points outside the site".to_string());
+    }
+    if target.is_dir() {
+        target = target.join("index.html");
+    }
+    if !target.is_file() {
+        return Err(format!("missing file {}", target.display()));
+    }
+
+    if let Some(fragment) = fragment.filter(|f| !f.is_empty() && *f != "top") {
+        let fragment = percent_decode_str(fragment).decode_utf8_lossy();
+        let found = ids
+            .get(&target)
+            .is_some_and(|page_ids| page_ids.contains(fragment.as_ref()));
+        if !found {
+            return Err(format!("missing anchor #{}", fragment));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the allowlist, ignoring blank lines and `#` comments.
+fn load_allowlist(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|contents| {
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads `url<TAB>unix-seconds` lines, keeping entries younger than `ttl`.
+fn load_cache(path: &Path, ttl: Duration) -> HashMap<String, u64> {
+    let now = unix_now();
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (url, checked) = line.split_once('\t')?;
+            let checked: u64 = checked.parse().ok()?;
+            (now.saturating_sub(checked) < ttl.as_secs()).then(|| (url.to_string(), checked))
+        })
+        .collect()
+}
+
+fn save_cache(path: &Path, cache: &HashMap<String, u64>) -> std::io::Result<()> {
+    let mut lines: Vec<String> = cache
+        .iter()
+        .map(|(url, checked)| format!("{}\t{}", url, checked))
+        .collect();
+    lines.sort();
+    fs::write(path, lines.join("\n") + "\n")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Fetches external URLs on a pool of `concurrency` threads.
+fn check_external(
+    links: &HashMap<String, Vec<PathBuf>>,
+    options: &CheckOptions,
+) -> Vec<BrokenLink> {
+    let allowlist = load_allowlist(&options.allowlist);
+    let mut cache = load_cache(&options.cache, options.cache_ttl);
+
+    let mut pending: Vec<String> = links
+        .keys()
+        .filter(|url| !allowlist.iter().any(|prefix| url.starts_with(prefix)))
+        .filter(|url| !cache.contains_key(*url))
+        .cloned()
+        .collect();
+    pending.sort();
+
+    println!(
+        "Checking {} external URLs ({} cached, {} allowlisted)",
+        pending.len(),
+        links.keys().filter(|url| cache.contains_key(*url)).count(),
+        links
+            .keys()
+            .filter(|url| allowlist.iter().any(|prefix| url.starts_with(prefix)))
+            .count()
+    );
+
+    let client = reqwest::blocking::Client::builder()
+        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .timeout(Duration::from_secs(15))
+        .build()
+        .unwrap();
+
+    let queue = Arc::new(Mutex::new(pending));
+    let (sender, receiver) = mpsc::channel();
+    let mut workers = Vec::new();
+    for _ in 0..options.concurrency.max(1) {
+        let queue = Arc::clone(&queue);
+        let sender = sender.clone();
+        let client = client.clone();
+        workers.push(std::thread::spawn(move || loop {
+            let Some(url) = queue.lock().unwrap().pop() else {
+                break;
+            };
+            let result = fetch(&client, &url);
+            if sender.send((url, result)).is_err() {
+                break;
+            }
+        }));
+    }
+    drop(sender);
+
+    let mut broken = Vec::new();
+    for (url, result) in receiver {
+        match result {
+            Ok(true) => {
+                cache.insert(url, unix_now());
+            }
+            Ok(false) => println!("Rate limited by {}, not caching the result", url),
+            Err(reason) => {
+                for page in &links[&url] {
+                    broken.push(BrokenLink {
+                        page: page.clone(),
+                        link: url.clone(),
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    if let Err(e) = save_cache(&options.cache, &cache) {
+        println!(
+            "Could not write link cache {}: {}",
+            options.cache.display(),
+            e
+        );
+    }
+
+    broken
+}
+
+/// Requests `url` with HEAD, retrying with GET for servers that reject HEAD.
+///
+/// `Ok(false)` means the server rate-limited us: not a failure, but not worth caching.
+fn fetch(client: &reqwest::blocking::Client, url: &str) -> Result<bool, String> {
+    let url = if url.starts_with("//") {
+        format!("https:{}", url)
+    } else {
+        url.to_string()
+    };
+
+    let response = match client.head(&url).send() {
+        Ok(response)
+            if matches!(response.status().as_u16(), 403 | 405 | 501)
+                || response.status().is_server_error() =>
+        {
+            client.get(&url).send()
+        }
+        other => other,
+    };
+
+    match response {
+        Ok(response) if response.status().is_success() => Ok(true),
+        // Rate limiting says nothing about whether the page exists
+        Ok(response) if response.status().as_u16() == 429 => Ok(false),
+        Ok(response) => Err(format!("HTTP {}", response.status())),
+        Err(e) => Err(e.to_string()),
+    }
+}
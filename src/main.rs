@@ -1,18 +1,59 @@
-use chrono;
 use clap::{Parser, Subcommand};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::read_to_string;
 use std::io::Write;
-use toml;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 // Import our custom modules
 mod markdown;
-use markdown::{parse_page_markdown, parse_post_markdown, Post};
+use markdown::{parse_page_markdown, parse_post_markdown, Page, Post};
 
 // Import the server module
 mod server;
-use server::start_server;
+use server::{start_server, ServeOptions};
+
+// Context-aware escaping for user content in generated markup
+mod escape;
+use escape::{html_attr, html_text, json_string, url_attr};
+
+// schema.org JSON-LD for posts and the home page
+mod structured_data;
+
+// Per-post Open Graph preview cards
+mod og_image;
+
+// `check-links` subcommand
+mod link_checker;
+
+// `init` and `new` subcommands
+mod scaffold;
+
+// `{{< name >}}` components expanded in Markdown
+mod shortcodes;
+
+// `> [!NOTE]` style callouts
+mod alerts;
+
+// TeX to MathML for posts with `math: true`
+mod math;
+
+// Thread pool for rendering posts and pages
+mod parallel;
+
+// ```dot``` and ```mermaid``` diagram blocks
+mod diagrams;
+
+// Numbered `[^label]` footnotes and sidenotes
+mod footnotes;
+
+// `diff`, `copy`, `anchors` and `remix` attributes on Solidity code fences
+mod code_blocks;
+
+// `[text](@/other-post.md#section)` links between content files
+mod cross_refs;
 
 /// Struct to hold site configuration loaded from `config.toml`
 #[derive(Deserialize)]
@@ -20,6 +61,22 @@ struct SiteConfig {
     site: SiteInfo,
     links: Links,
     analytics: Analytics,
+    #[serde(default)]
+    og_images: OgImages,
+    #[serde(default)]
+    robots: Robots,
+    #[serde(default)]
+    humans: Humans,
+    #[serde(default)]
+    security: Security,
+    #[serde(default)]
+    build: BuildPaths,
+    #[serde(default)]
+    alerts: Alerts,
+    #[serde(default)]
+    footnotes: Footnotes,
+    #[serde(default)]
+    markdown_links: MarkdownLinks,
 }
 
 /// Holds site metadata like title, description, etc.
@@ -47,8 +104,285 @@ struct Analytics {
     cloudflare_beacon_token: String,
 }
 
+/// Settings for the per-post Open Graph cards written to `docs/og/`
+#[derive(Deserialize)]
+#[serde(default)]
+struct OgImages {
+    enabled: bool,
+    background: String,
+    background_image: Option<String>,
+    text_color: String,
+    accent_color: String,
+    font: Option<String>,
+}
+
+impl Default for OgImages {
+    fn default() -> Self {
+        OgImages {
+            enabled: true,
+            background: "#11191f".to_string(),
+            background_image: None,
+            text_color: "#ffffff".to_string(),
+            accent_color: "#1095c1".to_string(),
+            font: None,
+        }
+    }
+}
+
+/// Rules written to `docs/robots.txt`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Robots {
+    /// Paths no crawler should fetch, e.g. "/drafts/"
+    disallow: Vec<String>,
+}
+
+/// Settings for `docs/humans.txt`
+#[derive(Deserialize)]
+#[serde(default)]
+struct Humans {
+    enabled: bool,
+    /// People or projects to credit under "THANKS"
+    thanks: Vec<String>,
+}
+
+impl Default for Humans {
+    fn default() -> Self {
+        Humans {
+            enabled: true,
+            thanks: Vec::new(),
+        }
+    }
+}
+
+/// Fields for `docs/.well-known/security.txt` (RFC 9116), written only when `contact` is set
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Security {
+    /// `mailto:` or `https:` URIs; bare email addresses get `mailto:` added
+    contact: Vec<String>,
+    /// RFC 3339 timestamp after which the file should be considered stale
+    expires: Option<String>,
+    policy: Option<String>,
+    encryption: Option<String>,
+    acknowledgments: Option<String>,
+    preferred_languages: Option<String>,
+    hiring: Option<String>,
+}
+
+/// Extra or restyled `> [!NAME]` alert kinds
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Alerts {
+    levels: Vec<AlertLevel>,
+}
+
+/// One `[[alerts.levels]]` entry; unset fields keep the built-in (or default) look
+#[derive(Deserialize)]
+struct AlertLevel {
+    /// Matched case-insensitively against `[!NAME]`, e.g. "high" for `[!HIGH]`
+    name: String,
+    title: Option<String>,
+    /// Icon name, e.g. "alert-octagon", "shield" or "flag"
+    icon: Option<String>,
+    /// CSS colour for the border and title
+    color: Option<String>,
+    /// ARIA role: "note" (default), "alert" or "status"
+    role: Option<String>,
+}
+
+/// How `[^label]` footnotes are laid out
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Footnotes {
+    /// Also show short notes in the margin on wide screens
+    sidenotes: bool,
+}
+
+/// How links in posts and pages are rewritten
+#[derive(Deserialize)]
+#[serde(default)]
+struct MarkdownLinks {
+    /// Open links to other sites in a new tab
+    new_tab: bool,
+    /// `rel` for links to other sites; empty to leave it off
+    rel: String,
+    /// Mark links to other sites with the `external-link` class, which adds an icon
+    icon: bool,
+    /// Point relative links to `.md` files at the pages built from them
+    rewrite_md: bool,
+}
+
+impl Default for MarkdownLinks {
+    fn default() -> Self {
+        MarkdownLinks {
+            new_tab: true,
+            rel: "noopener noreferrer nofollow".to_string(),
+            icon: true,
+            rewrite_md: true,
+        }
+    }
+}
+
+/// Input and output directories, relative to the directory holding `config.toml`
+#[derive(Deserialize)]
+#[serde(default)]
+struct BuildPaths {
+    source: String,
+    output: String,
+}
+
+impl Default for BuildPaths {
+    fn default() -> Self {
+        BuildPaths {
+            source: "content".to_string(),
+            output: "docs".to_string(),
+        }
+    }
+}
+
+/// Configuration plus the resolved input/output paths, passed to every generator
+struct BuildContext {
+    config: SiteConfig,
+    /// Directory holding `config.toml`; `archetypes/` is looked up here
+    root_dir: PathBuf,
+    /// Markdown sources (`content` by default)
+    source_dir: PathBuf,
+    /// Generated site (`docs` by default)
+    output_dir: PathBuf,
+    /// Source files behind each generated page, written to `.build-deps`
+    dependencies: Mutex<Vec<(String, Vec<PathBuf>)>>,
+    /// Code blocks that failed to render (e.g. a missing `file=`), which
+    /// fail the build once everything is rendered and before anything is
+    /// written
+    code_block_errors: Mutex<Vec<String>>,
+    /// Site-relative URL of every post and page in this build, by canonical
+    /// source path; filled in by `build` before anything is rendered
+    content_urls: HashMap<PathBuf, String>,
+    /// `@/` links seen while rendering, checked once everything is rendered
+    cross_refs: cross_refs::CrossRefs,
+}
+
+impl BuildContext {
+    /// Resolves `--config`, `--source` and `--output` against `config.toml`
+    ///
+    /// Command-line paths are relative to the working directory; paths from
+    /// the `[build]` section are relative to the config file.
+    fn load(cli: &Cli) -> BuildContext {
+        let config_path = cli
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("config.toml"));
+        let config = load_config(&config_path);
+        let root_dir = config_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let source_dir = cli
+            .source
+            .clone()
+            .unwrap_or_else(|| root_dir.join(&config.build.source));
+        let output_dir = cli
+            .output
+            .clone()
+            .unwrap_or_else(|| root_dir.join(&config.build.output));
+
+        BuildContext {
+            config,
+            root_dir,
+            source_dir,
+            output_dir,
+            dependencies: Mutex::new(Vec::new()),
+            code_block_errors: Mutex::new(Vec::new()),
+            content_urls: HashMap::new(),
+            cross_refs: cross_refs::CrossRefs::default(),
+        }
+    }
+
+    /// Path of a file in the output directory, e.g. `output_path("/about.html")`
+    fn output_path(&self, relative: &str) -> String {
+        self.output_dir
+            .join(relative.trim_start_matches('/'))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// Writes a generated file into the output directory and reports it
+    fn write_output(&self, relative: &str, contents: &str) {
+        let path = self.output_path(relative);
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)
+                .unwrap_or_else(|e| panic!("Failed to create {}: {}", parent.display(), e));
+        }
+        let mut file =
+            fs::File::create(&path).unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e));
+        file.write_all(contents.as_bytes())
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", path, e));
+
+        println!("Generated: {}", path);
+    }
+
+    /// Reports that `file_path` (the `what`, e.g. "post") failed to render.
+    /// Broken code blocks are kept to fail the build instead, so each is
+    /// reported once
+    fn parse_error(&self, what: &str, file_path: &str, e: Box<dyn std::error::Error>) {
+        match e.downcast::<code_blocks::BrokenCodeBlock>() {
+            Ok(broken) => self
+                .code_block_errors
+                .lock()
+                .unwrap()
+                .push(format!("{}: {}", file_path, broken)),
+            Err(e) => println!("Error parsing {} {}: {}", what, file_path, e),
+        }
+    }
+
+    /// Notes that the output file `output` was generated from `source` and the
+    /// files it includes
+    fn record_dependencies(&self, output: &str, source: &str, includes: &[PathBuf]) {
+        let mut sources = vec![PathBuf::from(source)];
+        sources.extend(includes.iter().cloned());
+        self.dependencies
+            .lock()
+            .unwrap()
+            .push((output.to_string(), sources));
+    }
+
+    /// Writes `.build-deps` next to `config.toml`: one Make-style
+    /// `output: sources...` line per page built from Markdown, so watchers and
+    /// incremental builds know what to redo when an included file changes
+    fn write_dependencies(&self) {
+        let mut dependencies = self.dependencies.lock().unwrap();
+        dependencies.sort();
+        let escape = |path: &str| path.replace(' ', "\\ ");
+        let contents: String = dependencies
+            .iter()
+            .map(|(output, sources)| {
+                let sources: Vec<String> = sources
+                    .iter()
+                    .map(|source| escape(&source.to_string_lossy()))
+                    .collect();
+                format!("{}: {}\n", escape(output), sources.join(" "))
+            })
+            .collect();
+
+        let path = self.root_dir.join(".build-deps");
+        if let Err(e) = fs::write(&path, contents) {
+            println!("Could not write {}: {}", path.display(), e);
+        }
+    }
+}
+
 #[derive(Parser)]
 struct Cli {
+    /// Site configuration file
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Directory containing the Markdown content (overrides `build.source`)
+    #[arg(long, global = true, value_name = "DIR")]
+    source: Option<PathBuf>,
+    /// Directory the site is generated into (overrides `build.output`)
+    #[arg(long, global = true, value_name = "DIR")]
+    output: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -56,156 +390,456 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Build the static site (parse Markdown & generate HTML)
-    Build,
-    /// Start a local server to preview the site at http://localhost:8464
-    Serve,
+    Build {
+        /// Files rendered at once (defaults to the number of CPUs)
+        #[arg(long, short)]
+        jobs: Option<usize>,
+    },
+    /// Start a local server to preview the site (http://127.0.0.1:8464 by default)
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8464)]
+        port: u16,
+        /// Address to bind; use 0.0.0.0 to expose the preview to your network
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Open the site in your browser once the server is up
+        #[arg(long)]
+        open: bool,
+        /// URL that replaces `base_url` in served pages (defaults to the local address)
+        #[arg(long, value_name = "URL")]
+        base_url: Option<String>,
+        /// Requests handled at once (defaults to the number of CPUs)
+        #[arg(long)]
+        workers: Option<usize>,
+    },
+    /// Create a new site in an empty directory
+    Init {
+        /// Directory to create the site in
+        dir: String,
+    },
+    /// Create a new post or page with its front matter filled in
+    New {
+        #[command(subcommand)]
+        kind: NewContent,
+    },
+    /// Check internal (and optionally external) links in the generated site
+    CheckLinks {
+        /// Also check external URLs over the network
+        #[arg(long)]
+        external: bool,
+        /// Number of external URLs checked in parallel
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+        /// File listing URL prefixes that are never fetched, one per line
+        #[arg(long, default_value = "link-allowlist.txt")]
+        allowlist: String,
+        /// File caching external URLs that were reachable
+        #[arg(long, default_value = ".link-check-cache")]
+        cache: String,
+        /// Hours before a cached external URL is checked again
+        #[arg(long, default_value_t = 24)]
+        cache_ttl: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum NewContent {
+    /// Create `content/<slug>.md` as a draft blog post
+    Post {
+        /// Title of the post; the file name is derived from it
+        title: String,
+        /// Template to use from `archetypes/` (defaults to `post`)
+        #[arg(long)]
+        archetype: Option<String>,
+    },
+    /// Create `content/<slug>.md` as a draft page (`type: page`), built to `docs/<slug>.html`
+    Page {
+        /// Title of the page; the file name is derived from it
+        title: String,
+        /// Template to use from `archetypes/` (defaults to `page`)
+        #[arg(long)]
+        archetype: Option<String>,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    // Load the configuration from `config.toml`
-    let config = load_config();
+    // `init` writes config.toml, so it has to run before we try to load one
+    if let Commands::Init { dir } = &cli.command {
+        match scaffold::init_site(std::path::Path::new(dir)) {
+            Ok(()) => println!(
+                "Site created in {}. Edit config.toml, then run `xeniria build` there.",
+                dir
+            ),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Load the configuration from `config.toml` and resolve the site's paths
+    let mut ctx = BuildContext::load(&cli);
+    let config = &ctx.config;
 
     match cli.command {
-        Commands::Build => {
+        Commands::Build { jobs } => {
             println!("Building site...");
 
-            // Ensure `docs/posts` folder exists
-            fs::create_dir_all("docs/posts")
-                .expect("Failed to create or verify docs/posts directory");
+            // A default would change the committed file on every build
+            if !config.security.contact.is_empty() && config.security.expires.is_none() {
+                eprintln!(
+                    "Build failed: security.expires is required when security.contact is set"
+                );
+                std::process::exit(1);
+            }
 
-            // Collect blog posts to build index.html
-            let mut posts_collected: Vec<Post> = Vec::new();
+            let jobs = jobs.unwrap_or_else(parallel::default_jobs);
+
+            // Posts and pages to render, as source path (and for pages, output URL and label)
+            let mut post_sources: Vec<String> = Vec::new();
+            let mut page_sources: Vec<(String, String, &str)> = Vec::new();
 
             // Scan `content/` for .md files
-            if let Ok(entries) = fs::read_dir("content") {
-                for entry in entries.flatten() {
-                    let path = entry.path();
-                    if path.extension().and_then(|e| e.to_str()) == Some("md") {
-                        let file_path = path.to_string_lossy().to_string();
-
-                        // Check special pages
-                        if file_path.ends_with("about.md") {
-                            generate_about(&file_path, &config);
-                        } else if file_path.ends_with("license.md") {
-                            generate_license(&file_path, &config);
-                        } else {
-                            // Treat everything else as a blog post
-                            match parse_post_markdown(&file_path) {
-                                Ok(post) => {
-                                    // Build the final HTML for this post using wrap_in_template
-                                    // We'll pass in the post's title and a custom body content.
-                                    let post_body = format!(
-                                        "<h1>{title}</h1>
-                                         <p><strong>By {author}</strong> - {date} - {read_time} min read</p>
-                                         {content}",
-                                        title = post.front_matter.title,
-                                        author = post.front_matter.author,
-                                        date = post.front_matter.date,
-                                        read_time = post.reading_time,
-                                        content = post.content,
-                                    );
-
-                                    let post_url = post
-                                        .file_name
-                                        .strip_prefix("docs")
-                                        .unwrap_or(&post.file_name);
-
-                                    let final_html = wrap_in_template(
-                                        &post.front_matter.title,
-                                        post.front_matter.description.as_deref().unwrap_or("A blog post generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria"),
-                                        &post_body,
-                                        "../",
-                                        post_url,
-                                        &config
-                                    );
-
-                                    // Write it out to post.file_name
-                                    let mut file = fs::File::create(&post.file_name)
-                                        .expect("Failed to create post file");
-                                    file.write_all(final_html.as_bytes())
-                                        .expect("Failed to write post file");
-
-                                    println!("Generated: {}", post.file_name);
-
-                                    // Add to list for index.html
-                                    posts_collected.push(post);
-                                }
-                                Err(e) => {
-                                    println!("Error parsing post {}: {}", file_path, e);
-                                }
-                            }
-                        }
+            for path in markdown_files(&ctx.source_dir) {
+                let file_path = path.to_string_lossy().to_string();
+
+                // Check special pages
+                if file_path.ends_with("about.md") {
+                    page_sources.push((file_path, "/about.html".to_string(), "About"));
+                } else if file_path.ends_with("license.md") {
+                    page_sources.push((file_path, "/license.html".to_string(), "License"));
+                } else if path.file_name().is_some_and(|name| name == "404.md") {
+                    // Rendered by generate_not_found below
+                } else if markdown::is_page(&file_path) {
+                    // `type: page`: `content/<slug>.md` becomes `docs/<slug>.html`
+                    let slug = path
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    if matches!(slug.as_str(), "index" | "posts") {
+                        println!(
+                            "Skipping {}: {}.html is generated by the build",
+                            file_path, slug
+                        );
+                    } else {
+                        page_sources.push((file_path, format!("/{}.html", slug), "Page"));
                     }
+                } else {
+                    // Treat everything else as a blog post
+                    post_sources.push(file_path);
                 }
             }
 
+            // Where each post and page ends up, so Markdown links between them
+            // can be rewritten; unparsable posts are reported when rendered
+            for (file_path, page_url, _) in &page_sources {
+                ctx.content_urls
+                    .insert(canonical_path(file_path), page_url.clone());
+            }
+            for file_path in &post_sources {
+                match markdown::read_post_front_matter(file_path) {
+                    Ok(front_matter) if front_matter.draft => {}
+                    Ok(front_matter) => {
+                        ctx.content_urls
+                            .insert(canonical_path(file_path), markdown::post_url(&front_matter));
+                    }
+                    Err(_) => {}
+                }
+            }
+
+            // Render everything before writing anything, so a broken code block
+            // or cross-reference leaves `docs` as it was. Each file renders
+            // independently (including fetching remote images), so pages and
+            // posts are spread over `jobs` threads; results keep the sorted order
+            let pages: Vec<(&(String, String, &str), Page)> = page_sources
+                .iter()
+                .zip(parallel::map(
+                    &page_sources,
+                    jobs,
+                    |(file_path, _, label)| read_page(file_path, label, &ctx),
+                ))
+                .filter_map(|(source, page)| Some((source, page?)))
+                .collect();
+            let posts: Vec<(&String, Post)> = post_sources
+                .iter()
+                .zip(parallel::map(&post_sources, jobs, |file_path| {
+                    read_post(file_path, &ctx)
+                }))
+                .filter_map(|(file_path, post)| Some((file_path, post?)))
+                .collect();
+            let not_found = read_not_found(&ctx);
+
+            let mut failures = Vec::new();
+            let mut code_block_errors = ctx.code_block_errors.lock().unwrap().clone();
+            code_block_errors.sort();
+            for error in &code_block_errors {
+                eprintln!("Broken code block: {}", error);
+            }
+            if !code_block_errors.is_empty() {
+                failures.push(format!("{} broken code block(s)", code_block_errors.len()));
+            }
+            let broken = ctx.cross_refs.errors();
+            for error in &broken {
+                eprintln!("Broken cross-reference: {}", error);
+            }
+            if !broken.is_empty() {
+                failures.push(format!("{} broken cross-reference(s)", broken.len()));
+            }
+            if !failures.is_empty() {
+                eprintln!("Build failed: {}", failures.join(", "));
+                std::process::exit(1);
+            }
+
+            // Ensure `docs/posts` folder exists
+            let posts_dir = ctx.output_dir.join("posts");
+            fs::create_dir_all(&posts_dir).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to create or verify {} directory: {}",
+                    posts_dir.display(),
+                    e
+                )
+            });
+
+            // Indexable special pages, listed in sitemap.xml
+            let page_entries: Vec<SitemapEntry> =
+                parallel::map(&pages, jobs, |((file_path, page_url, label), page)| {
+                    generate_page(page, file_path, page_url, label, &ctx)
+                })
+                .into_iter()
+                .flatten()
+                .collect();
+
+            // Posts also render their OG cards, so they're written in parallel too
+            parallel::map(&posts, jobs, |(file_path, post)| {
+                generate_post(post, file_path, &ctx)
+            });
+            // Collect blog posts to build index.html
+            let posts_collected: Vec<Post> = posts.into_iter().map(|(_, post)| post).collect();
+
+            generate_not_found(not_found.as_ref(), &ctx);
+            ctx.write_dependencies();
+
             // Generate index.html to link to all posts
-            generate_index(&posts_collected, &config);
+            generate_index(&posts_collected, &ctx);
+
+            generate_posts(&posts_collected, &ctx);
 
-            generate_posts(&posts_collected, &config);
+            generate_sitemap(
+                &sitemap_entries(&posts_collected, page_entries, config),
+                &ctx,
+            );
+
+            generate_robots(&ctx);
 
-            generate_sitemap(&posts_collected, &config);
+            // GitHub Pages runs Jekyll by default, which drops `.well-known/`
+            ctx.write_output(".nojekyll", "");
+
+            if config.humans.enabled {
+                generate_humans(&posts_collected, &ctx);
+            }
+
+            if !config.security.contact.is_empty() {
+                generate_security_txt(&ctx);
+            }
 
             println!("Site build complete!");
         }
 
-        Commands::Serve => {
-            // Start server on a custom port
-            println!("Starting server at http://localhost:8464...");
-            let port = 8464;
-            if let Err(e) = start_server(port) {
+        Commands::Serve {
+            port,
+            bind,
+            open,
+            base_url,
+            workers,
+        } => {
+            // Canonical/OG links point at `base_url`; send them to the preview instead
+            let local = base_url.unwrap_or_else(|| server::local_url(&bind, port));
+            let production = config.site.base_url.trim_end_matches('/').to_string();
+            let rewrite_base_url = (!production.is_empty())
+                .then(|| (production, local.trim_end_matches('/').to_string()));
+
+            let options = ServeOptions {
+                root: ctx.output_dir.clone(),
+                bind,
+                port,
+                open,
+                rewrite_base_url,
+                workers: workers
+                    .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get())),
+            };
+            if let Err(e) = start_server(&options) {
                 eprintln!("Server error: {}", e);
             }
         }
+
+        // Handled above, before loading the configuration
+        Commands::Init { .. } => {}
+
+        Commands::New { kind } => {
+            let (kind, title, archetype) = match kind {
+                NewContent::Post { title, archetype } => {
+                    (scaffold::ContentKind::Post, title, archetype)
+                }
+                NewContent::Page { title, archetype } => {
+                    (scaffold::ContentKind::Page, title, archetype)
+                }
+            };
+            match scaffold::new_content(kind, &title, archetype.as_deref(), &ctx) {
+                Ok(path) => println!("Created: {}", path.display()),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::CheckLinks {
+            external,
+            concurrency,
+            allowlist,
+            cache,
+            cache_ttl,
+        } => {
+            let options = link_checker::CheckOptions {
+                root: ctx.output_dir.clone(),
+                base_url: config.site.base_url.clone(),
+                external,
+                concurrency,
+                allowlist: allowlist.into(),
+                cache: cache.into(),
+                cache_ttl: std::time::Duration::from_secs(cache_ttl * 3600),
+            };
+            match link_checker::check_links(&options) {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Link check error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
     }
 }
 
-/// Load configuration from `config.toml`
-fn load_config() -> SiteConfig {
-    let config_contents = read_to_string("config.toml").expect("Failed to read config.toml");
-    toml::from_str(&config_contents).expect("Failed to parse config.toml")
+/// Load configuration from `config.toml` (or the file given with `--config`)
+fn load_config(path: &Path) -> SiteConfig {
+    let config_contents =
+        read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let config: SiteConfig = toml::from_str(&config_contents)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+    alerts::validate(&config.alerts)
+        .unwrap_or_else(|e| panic!("Invalid [[alerts.levels]] in {}: {}", path.display(), e));
+    config
+}
+
+/// Joins a site-relative path onto `base_url`
+fn absolute_url(base_url: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Absolute URL of the default preview image: `og_image` if present, else `profile_picture`
+fn default_og_image_url(config: &SiteConfig) -> String {
+    let og_image_path = config
+        .site
+        .og_image
+        .as_deref()
+        .unwrap_or(&config.site.profile_picture);
+    absolute_url(&config.site.base_url, og_image_path)
+}
+
+/// Absolute URL of a post's preview image
+///
+/// Uses the front matter `image` if set, otherwise renders a card into
+/// `docs/og/<slug>.png`, falling back to the site-wide image on failure.
+fn post_og_image_url(post: &Post, ctx: &BuildContext) -> String {
+    let config = &ctx.config;
+    if let Some(image) = &post.front_matter.image {
+        if image.starts_with("http://") || image.starts_with("https://") {
+            return image.clone();
+        }
+        return absolute_url(&config.site.base_url, image);
+    }
+
+    if !config.og_images.enabled {
+        return default_og_image_url(config);
+    }
+
+    let slug = Path::new(&post.file_name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let card_path = ctx.output_path(&format!("og/{}.png", slug));
+
+    match og_image::generate_card(post, Path::new(&card_path), ctx) {
+        Ok(()) => {
+            println!("Generated: {}", card_path);
+            absolute_url(&config.site.base_url, &format!("og/{}.png", slug))
+        }
+        Err(e) => {
+            println!("Error generating OG image {}: {}", card_path, e);
+            default_og_image_url(config)
+        }
+    }
 }
 
 /// Helper function to generate the full HTML layout
 /// Takes a `title` (for <title>) and a `body_content` (the unique page/post body).
+/// `title` and `meta_description` are raw values and are escaped here;
+/// `body_content` and `extra_head` (JSON-LD `<script>` blocks, robots meta)
+/// are already-rendered HTML and are inserted as is. `og_image_url` overrides the
+/// site-wide preview image when set.
+#[allow(clippy::too_many_arguments)]
 fn wrap_in_template(
     title: &str,
     meta_description: &str,
     body_content: &str,
     prefix: &str,
     page_url: &str,
+    extra_head: &str,
+    og_image_url: Option<&str>,
     config: &SiteConfig,
 ) -> String {
     // Optional Plausible Analytics script
     let plausible_script = if !config.analytics.plausible_domain.is_empty() {
         format!(
         "<script async defer data-domain=\"{}\" src=\"https://plausible.io/js/script.js\"></script>",
-        config.analytics.plausible_domain
+        html_attr(&config.analytics.plausible_domain)
     )
     } else {
         String::new()
     };
     let cloudflare_script = if !config.analytics.cloudflare_beacon_token.is_empty() {
         format!(
-        "<script defer src='https://static.cloudflareinsights.com/beacon.min.js' data-cf-beacon='{{\"token\": \"{}\"}}'></script>",
-        config.analytics.cloudflare_beacon_token
+        "<script defer src='https://static.cloudflareinsights.com/beacon.min.js' data-cf-beacon='{}'></script>",
+        html_attr(&format!(
+            "{{\"token\": \"{}\"}}",
+            json_string(&config.analytics.cloudflare_beacon_token)
+        ))
     )
     } else {
         String::new()
     };
-    // Determine OG image URL: use og_image if present, else profile_picture
-    let og_image_path = config
-        .site
-        .og_image
-        .as_deref()
-        .unwrap_or(&config.site.profile_picture);
-    let og_image_url = format!(
-        "{}/{}",
-        config.site.base_url.trim_end_matches('/'),
-        og_image_path.trim_start_matches('/')
-    );
+    // Only pages with a `copy` code block need the copy button handler
+    let code_blocks_script = if body_content.contains("class=\"code-copy\"") {
+        format!(
+            "<script defer src='{}assets/js/code-blocks.js'></script>",
+            prefix
+        )
+    } else {
+        String::new()
+    };
+    let og_image_url = match og_image_url {
+        Some(url) => url_attr(url),
+        None => url_attr(&default_og_image_url(config)),
+    };
 
     // Twitter image URL is same — Twitter and OG should match
     let twitter_image_url = og_image_url.clone();
@@ -218,16 +852,16 @@ fn wrap_in_template(
     <meta name='viewport' content='width=device-width, initial-scale=1.0'>
     <meta name='description' content='{meta_description}'>
     <meta name='generator' content='Xeniria — https://github.com/0xh4ty/xeniria'>
-    <title>{title}</title>
+    <title>{title_text}</title>
 
     <!-- Canonical URL -->
-    <link rel='canonical' href='{base_url}{page_url}'>
+    <link rel='canonical' href='{canonical_url}'>
 
     <!-- Open Graph (OG) meta -->
     <meta property='og:title' content='{title}'>
     <meta property='og:description' content='{meta_description}'>
     <meta property='og:type' content='article'>
-    <meta property='og:url' content='{base_url}{page_url}'>
+    <meta property='og:url' content='{canonical_url}'>
     <meta property='og:image' content='{og_image_url}'>
 
     <!-- Twitter meta -->
@@ -236,6 +870,8 @@ fn wrap_in_template(
     <meta name='twitter:description' content='{meta_description}'>
     <meta name='twitter:image' content='{twitter_image_url}'>
 
+    {extra_head}
+
     <link rel='stylesheet' href='{prefix}assets/css/pico.min.css'>
     <link rel='stylesheet' href='{prefix}assets/styles.css'>
     <link rel='stylesheet' href='{prefix}assets/prism-custom-theme.css'>
@@ -244,6 +880,7 @@ fn wrap_in_template(
     <script src='{prefix}assets/prism/prism.min.js'></script>
     <script src='{prefix}assets/prism/prism-solidity.min.js'></script>
     <script src='{prefix}assets/prism/prism-line-numbers.min.js'></script>
+    {code_blocks_script}
 
     {plausible_script}
     {cloudflare_script}
@@ -294,92 +931,268 @@ fn wrap_in_template(
 </script>
 </body>
 </html>",
-        title = title,
-        meta_description = meta_description,
-        base_url = config.site.base_url,
-        page_url = page_url,
-        prefix = prefix,
+        title_text = html_text(title),
+        title = html_attr(title),
+        meta_description = html_attr(meta_description),
+        canonical_url = url_attr(&format!("{}{}", config.site.base_url, page_url)),
+        prefix = html_attr(prefix),
+        extra_head = extra_head,
         body_content = body_content,
-        author = config.site.author,
-        github = config.links.github,
-        twitter = config.links.twitter
+        author = html_text(&config.site.author),
+        github = url_attr(&config.links.github),
+        twitter = url_attr(&config.links.twitter)
     )
 }
 
-/// Generate `about.html` from `about.md`
-fn generate_about(file_path: &str, config: &SiteConfig) {
-    match parse_page_markdown(file_path) {
-        Ok(page) => {
-            // Prepare a body with a heading, author, and page.content
-            let about_body = format!(
-                "<h1>{title}</h1>
-                 <p>By {author}</p>
+/// `<meta name='robots'>` tag for pages kept out of search engines
+fn robots_meta(noindex: bool) -> &'static str {
+    if noindex {
+        "<meta name='robots' content='noindex'>\n    "
+    } else {
+        ""
+    }
+}
+
+/// Render a blog post from a Markdown file in `content/`
+///
+/// Returns the parsed post for the index, feeds and sitemap, or `None` if it
+/// is a skipped draft or failed to parse.
+fn read_post(file_path: &str, ctx: &BuildContext) -> Option<Post> {
+    match parse_post_markdown(file_path, ctx) {
+        Ok(post) if post.front_matter.draft => {
+            println!("Skipping draft: {}", file_path);
+            None
+        }
+        Ok(post) => Some(post),
+        Err(e) => {
+            ctx.parse_error("post", file_path, e);
+            None
+        }
+    }
+}
+
+/// Write a post rendered by `read_post` from `file_path`, with its OG card
+fn generate_post(post: &Post, file_path: &str, ctx: &BuildContext) {
+    let config = &ctx.config;
+    // Build the final HTML for this post using wrap_in_template
+    // We'll pass in the post's title and a custom body content.
+    let post_body = format!(
+        "<h1>{title}</h1>
+                 <p><strong>By {author}</strong> - {date} - {read_time} min read</p>
                  {content}",
-                title = page.front_matter.title,
-                author = page.front_matter.author,
-                content = page.content
-            );
+        title = html_text(&post.front_matter.title),
+        author = html_text(&post.front_matter.author),
+        date = html_text(&post.front_matter.date),
+        read_time = post.reading_time,
+        content = post.content,
+    );
+
+    let post_url = post.url.as_str();
+
+    let og_image_url = post_og_image_url(post, ctx);
+
+    // JSON-LD: the post itself plus Home > Posts > post breadcrumbs
+    let extra_head = format!(
+        "{}{}\n    {}",
+        robots_meta(post.front_matter.noindex),
+        structured_data::blog_posting(post, post_url, &og_image_url, config),
+        structured_data::breadcrumbs(
+            &[
+                ("Home", "/"),
+                ("Posts", "/posts.html"),
+                (&post.front_matter.title, post_url),
+            ],
+            config
+        )
+    );
+
+    let final_html = wrap_in_template(
+        &post.front_matter.title,
+        post.front_matter.description.as_deref().unwrap_or("A blog post generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria"),
+        &post_body,
+        "../",
+        post_url,
+        &extra_head,
+        Some(&og_image_url),
+        config
+    );
+
+    // Write it out to post.file_name
+    let mut file = fs::File::create(&post.file_name).expect("Failed to create post file");
+    file.write_all(final_html.as_bytes())
+        .expect("Failed to write post file");
 
-            let final_html = wrap_in_template(&page.front_matter.title, page.front_matter.description.as_deref().unwrap_or("About page generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria"), &about_body, "", "/about.html", config);
+    println!("Generated: {}", post.file_name);
+    ctx.record_dependencies(&post.file_name, file_path, &post.includes);
+}
 
-            let mut file =
-                fs::File::create("docs/about.html").expect("Failed to create about.html");
-            file.write_all(final_html.as_bytes())
-                .expect("Failed to write about.html");
+/// `path` with symlinks and `.`/`..` resolved, or as given if it doesn't exist
+fn canonical_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
 
-            println!("Generated: docs/about.html");
+/// Markdown files directly inside `dir`, sorted so builds are reproducible
+fn markdown_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("md"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Render a standalone page (About, License, or any `type: page` file)
+///
+/// `label` names the page in messages. Returns `None` if it is a skipped draft
+/// or failed to parse.
+fn read_page(file_path: &str, label: &str, ctx: &BuildContext) -> Option<Page> {
+    match parse_page_markdown(file_path, ctx) {
+        Ok(page) if page.front_matter.draft => {
+            println!("Skipping draft: {}", file_path);
+            None
         }
+        Ok(page) => Some(page),
         Err(e) => {
-            println!("Error parsing About page {}: {}", file_path, e);
+            ctx.parse_error(&format!("{} page", label), file_path, e);
+            None
         }
     }
 }
 
-/// Generate `license.html` from `license.md`
-fn generate_license(file_path: &str, config: &SiteConfig) {
-    match parse_page_markdown(file_path) {
-        Ok(page) => {
-            let license_body = format!(
-                "<h1>{title}</h1>
+/// Write a page rendered by `read_page` from `file_path`
+///
+/// `page_url` is the output path relative to `docs`, e.g. "/about.html", and
+/// `label` names the page in the fallback description. Returns the page's
+/// sitemap entry unless it is marked `noindex`.
+fn generate_page(
+    page: &Page,
+    file_path: &str,
+    page_url: &str,
+    label: &str,
+    ctx: &BuildContext,
+) -> Option<SitemapEntry> {
+    let config = &ctx.config;
+
+    // Prepare a body with a heading, author, and page.content
+    let page_body = format!(
+        "<h1>{title}</h1>
                  <p>By {author}</p>
                  {content}",
-                title = page.front_matter.title,
-                author = page.front_matter.author,
-                content = page.content
-            );
+        title = html_text(&page.front_matter.title),
+        author = html_text(&page.front_matter.author),
+        content = page.content
+    );
 
-            let final_html = wrap_in_template(&page.front_matter.title, page.front_matter.description.as_deref().unwrap_or("License page generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria"), &license_body, "", "/license.html", config);
+    let fallback_description = format!("{} page generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria", label);
+    let final_html = wrap_in_template(
+        &page.front_matter.title,
+        page.front_matter
+            .description
+            .as_deref()
+            .unwrap_or(&fallback_description),
+        &page_body,
+        "",
+        page_url,
+        robots_meta(page.front_matter.noindex),
+        None,
+        config,
+    );
 
-            let mut file =
-                fs::File::create("docs/license.html").expect("Failed to create license.html");
-            file.write_all(final_html.as_bytes())
-                .expect("Failed to write license.html");
+    ctx.write_output(page_url, &final_html);
+    ctx.record_dependencies(&ctx.output_path(page_url), file_path, &page.includes);
 
-            println!("Generated: docs/license.html");
-        }
-        Err(e) => {
-            println!("Error parsing License page {}: {}", file_path, e);
-        }
+    page_sitemap_entry(page_url, file_path, page, config)
+}
+
+/// Render `content/404.md`, or `None` if there is none (or it's a draft or
+/// failed to parse)
+fn read_not_found(ctx: &BuildContext) -> Option<Page> {
+    let source = ctx.source_dir.join("404.md");
+    if !source.is_file() {
+        return None;
+    }
+    read_page(&source.to_string_lossy(), "404", ctx)
+}
+
+/// Generate `404.html` from the page rendered by `read_not_found`, or a
+/// built-in "page not found" message if there is none
+///
+/// Hosts (and `serve`) return this page for any missing URL, at any depth, so
+/// its links are rooted at `base_url` instead of being relative. It is never
+/// indexed or listed in the sitemap.
+fn generate_not_found(page: Option<&Page>, ctx: &BuildContext) {
+    let config = &ctx.config;
+    let prefix = format!("{}/", config.site.base_url.trim_end_matches('/'));
+    let source = ctx.source_dir.join("404.md");
+
+    let (title, description, body) = match page {
+        Some(page) => (
+            page.front_matter.title.as_str(),
+            page.front_matter.description.as_deref(),
+            format!(
+                "<h1>{title}</h1>
+                 {content}",
+                title = html_text(&page.front_matter.title),
+                content = page.content
+            ),
+        ),
+        None => (
+            "Page not found",
+            None,
+            format!(
+                "<h1>Page not found</h1>
+                 <p>The page you're looking for doesn't exist or has moved.</p>
+                 <p><a href='{}index.html'>Back to the home page</a></p>",
+                html_attr(&prefix)
+            ),
+        ),
+    };
+
+    let final_html = wrap_in_template(
+        title,
+        description.unwrap_or("The requested page could not be found."),
+        &body,
+        &prefix,
+        "/404.html",
+        robots_meta(true),
+        None,
+        config,
+    );
+    ctx.write_output("/404.html", &final_html);
+    if let Some(page) = page {
+        ctx.record_dependencies(
+            &ctx.output_path("/404.html"),
+            &source.to_string_lossy(),
+            &page.includes,
+        );
     }
 }
 
 /// Generate `index.html` listing all blog posts
-fn generate_index(posts: &Vec<Post>, config: &SiteConfig) {
+fn generate_index(posts: &[Post], ctx: &BuildContext) {
+    let config = &ctx.config;
     // Clone & sort posts by date DESC (assuming YYYY-MM-DD format)
-    let mut sorted_posts = posts.clone();
+    let mut sorted_posts = posts.to_vec();
     sorted_posts.sort_by(|a, b| b.front_matter.date.cmp(&a.front_matter.date));
 
     let author_html = if config.site.author_glitch_effect {
         format!(
-            "<h2 class='profile-name hero glitch layers' data-text='{author}'>
+            "<h2 class='profile-name hero glitch layers' data-text='{author_attr}'>
                 <span>{author}</span>
             </h2>",
-            author = config.site.author
+            author_attr = html_attr(&config.site.author),
+            author = html_text(&config.site.author)
         )
     } else {
         format!(
             "<h2 class='profile-name'>{author}</h2>",
-            author = config.site.author
+            author = html_text(&config.site.author)
         )
     };
 
@@ -393,14 +1206,14 @@ fn generate_index(posts: &Vec<Post>, config: &SiteConfig) {
         <div class='recent-posts'>
         <h3>Recent Posts</h3>
         <ul>",
-        profile_picture = config.site.profile_picture,
+        profile_picture = url_attr(&config.site.profile_picture),
         author_html = author_html,
-        description = config.site.description,
+        description = html_text(&config.site.description),
     );
 
     for post in sorted_posts.iter().take(5) {
         recent_posts_html.push_str("<hr>\n");
-        let link_path = post.file_name.replace("docs/", "");
+        let link_path = post.url.trim_start_matches('/');
         recent_posts_html.push_str(&format!(
             "<li class='post-item'>
                 <span class='post-title'>
@@ -410,9 +1223,9 @@ fn generate_index(posts: &Vec<Post>, config: &SiteConfig) {
                     {date}
                 </span>
             </li>\n",
-            link = link_path,
-            title = post.front_matter.title,
-            date = post.front_matter.date
+            link = url_attr(link_path),
+            title = html_text(&post.front_matter.title),
+            date = html_text(&post.front_matter.date)
         ));
     }
 
@@ -431,21 +1244,20 @@ fn generate_index(posts: &Vec<Post>, config: &SiteConfig) {
         &recent_posts_html,
         "",
         "/",
+        &structured_data::website(config),
+        None,
         config,
     );
 
     // Write to `docs/index.html`
-    let mut file = fs::File::create("docs/index.html").expect("Failed to create docs/index.html");
-    file.write_all(final_html.as_bytes())
-        .expect("Failed to write index.html");
-
-    println!("Generated: docs/index.html");
+    ctx.write_output("index.html", &final_html);
 }
 
 /// Generate `posts.html` listing all posts grouped by year
-fn generate_posts(posts: &Vec<Post>, config: &SiteConfig) {
+fn generate_posts(posts: &[Post], ctx: &BuildContext) {
+    let config = &ctx.config;
     // Clone & sort posts by date DESC (newest first)
-    let mut sorted_posts = posts.clone();
+    let mut sorted_posts = posts.to_vec();
     sorted_posts.sort_by(|a, b| b.front_matter.date.cmp(&a.front_matter.date));
 
     // Start HTML content
@@ -459,20 +1271,23 @@ fn generate_posts(posts: &Vec<Post>, config: &SiteConfig) {
 
         // If it's a new year, add a heading with extra spacing
         if post_year != last_year {
-            posts_html.push_str(&format!("<h3 class='post-year'>{}</h3>\n", post_year));
+            posts_html.push_str(&format!(
+                "<h3 class='post-year'>{}</h3>\n",
+                html_text(post_year)
+            ));
             last_year = post_year.to_string();
         }
 
-        let link_path = post.file_name.replace("docs/", "");
+        let link_path = post.url.trim_start_matches('/');
         posts_html.push_str(&format!(
             "<hr>\n\
              <div class='post-item'>\n\
                 <a href='{link}' class='post-title'>{title}</a>\n\
                 <span class='post-date'>{date}</span>\n\
             </div>\n",
-            link = link_path,
-            title = post.front_matter.title,
-            date = post.front_matter.date
+            link = url_attr(link_path),
+            title = html_text(&post.front_matter.title),
+            date = html_text(&post.front_matter.date)
         ));
     }
 
@@ -480,54 +1295,348 @@ fn generate_posts(posts: &Vec<Post>, config: &SiteConfig) {
     posts_html.push_str("</div>\n");
 
     // Wrap in template
-    let final_html = wrap_in_template("All Posts", "Posts page generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria", &posts_html, "", "/posts.html", config);
+    let final_html = wrap_in_template("All Posts", "Posts page generated by Xeniria — a fast, minimal Rust-based static site generator. https://github.com/0xh4ty/xeniria", &posts_html, "", "/posts.html", "", None, config);
 
     // Write to `docs/posts.html`
-    let mut file = fs::File::create("docs/posts.html").expect("Failed to create docs/posts.html");
-    file.write_all(final_html.as_bytes())
-        .expect("Failed to write posts.html");
+    ctx.write_output("posts.html", &final_html);
+}
+
+/// A URL listed in `sitemap.xml`
+struct SitemapEntry {
+    /// Site-relative URL, e.g. "/posts/my-title.html"
+    path: String,
+    /// Last modification date as YYYY-MM-DD
+    lastmod: Option<String>,
+    changefreq: Option<String>,
+    priority: Option<f32>,
+    /// Absolute URLs of images shown on the page
+    images: Vec<String>,
+}
+
+/// A single sitemap file may hold at most 50,000 URLs; beyond that we write a sitemap index
+const MAX_SITEMAP_URLS: usize = 50_000;
 
-    println!("Generated: docs/posts.html");
+/// Normalises a front matter date ("2025-01-30" or RFC 3339) to YYYY-MM-DD
+fn sitemap_date(value: &str) -> Option<String> {
+    let date = value.get(..10)?;
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|d| d.format("%Y-%m-%d").to_string())
 }
 
-fn generate_sitemap(posts: &Vec<Post>, config: &SiteConfig) {
-    let base_url = &config.site.base_url;
+/// Modification time of a source file as YYYY-MM-DD
+fn file_lastmod(file_path: &str) -> Option<String> {
+    let modified = fs::metadata(file_path).ok()?.modified().ok()?;
+    Some(
+        chrono::DateTime::<chrono::Utc>::from(modified)
+            .format("%Y-%m-%d")
+            .to_string(),
+    )
+}
+
+/// Keeps only the `changefreq` values defined by the sitemap protocol
+fn sitemap_changefreq(value: Option<&str>, file_path: &str) -> Option<String> {
+    const VALID: [&str; 7] = [
+        "always", "hourly", "daily", "weekly", "monthly", "yearly", "never",
+    ];
+    let value = value?;
+    if VALID.contains(&value) {
+        Some(value.to_string())
+    } else {
+        println!(
+            "Ignoring invalid changefreq '{}' in {} (expected one of {})",
+            value,
+            file_path,
+            VALID.join(", ")
+        );
+        None
+    }
+}
+
+/// Absolute URL of an image `src` as written in a post or page
+fn sitemap_image_url(src: &str, config: &SiteConfig) -> String {
+    if src.starts_with("http://") || src.starts_with("https://") {
+        src.to_string()
+    } else {
+        absolute_url(&config.site.base_url, src.trim_start_matches("../"))
+    }
+}
+
+/// Sitemap entry for a special page, or `None` if it is marked `noindex`
+///
+/// `lastmod` comes from `updated`/`date` in the front matter, falling back to
+/// the source file's modification time.
+fn page_sitemap_entry(
+    path: &str,
+    file_path: &str,
+    page: &markdown::Page,
+    config: &SiteConfig,
+) -> Option<SitemapEntry> {
+    let front_matter = &page.front_matter;
+    if front_matter.noindex {
+        return None;
+    }
+    Some(SitemapEntry {
+        path: path.to_string(),
+        lastmod: front_matter
+            .updated
+            .as_deref()
+            .or(front_matter.date.as_deref())
+            .and_then(sitemap_date)
+            .or_else(|| file_lastmod(file_path)),
+        changefreq: sitemap_changefreq(front_matter.changefreq.as_deref(), file_path),
+        priority: front_matter.priority,
+        images: page
+            .images
+            .iter()
+            .map(|src| sitemap_image_url(src, config))
+            .collect(),
+    })
+}
+
+/// Every indexable URL on the site: home, special pages, the archive and posts
+///
+/// Posts marked `noindex` are skipped. The home page and archive take the
+/// newest `lastmod` of the posts they list.
+fn sitemap_entries(
+    posts: &[Post],
+    page_entries: Vec<SitemapEntry>,
+    config: &SiteConfig,
+) -> Vec<SitemapEntry> {
+    let post_entries: Vec<SitemapEntry> = posts
+        .iter()
+        .filter(|post| !post.front_matter.noindex)
+        .map(|post| {
+            let front_matter = &post.front_matter;
+            SitemapEntry {
+                path: post.url.clone(),
+                lastmod: sitemap_date(
+                    front_matter
+                        .updated
+                        .as_deref()
+                        .unwrap_or(&front_matter.date),
+                ),
+                changefreq: sitemap_changefreq(front_matter.changefreq.as_deref(), &post.file_name),
+                priority: front_matter.priority,
+                images: post
+                    .images
+                    .iter()
+                    .map(|src| sitemap_image_url(src, config))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let newest_post = post_entries.iter().filter_map(|e| e.lastmod.clone()).max();
+    let listing = |path: &str| SitemapEntry {
+        path: path.to_string(),
+        lastmod: newest_post.clone(),
+        changefreq: None,
+        priority: None,
+        images: Vec::new(),
+    };
+
+    let mut entries = vec![listing("/")];
+    entries.extend(page_entries);
+    entries.push(listing("/posts.html"));
+    entries.extend(post_entries);
+    entries
+}
+
+/// Renders one `<urlset>` sitemap file
+fn render_urlset(entries: &[SitemapEntry], config: &SiteConfig) -> String {
+    let base_url = html_text(&config.site.base_url);
     // Start XML
     let mut sitemap = String::from(
         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
-         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" \
+         xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\">\n",
     );
 
-    // Static pages
-    let pages = vec!["/", "/about.html", "/license.html", "/posts.html"];
-
-    for path in pages {
+    for entry in entries {
         sitemap.push_str(&format!(
-            "  <url>\n    <loc>{}{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
+            "  <url>\n    <loc>{}{}</loc>\n",
             base_url,
-            path,
-            chrono::Utc::now().format("%Y-%m-%d")
+            html_text(&entry.path)
         ));
+        if let Some(lastmod) = &entry.lastmod {
+            sitemap.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod));
+        }
+        if let Some(changefreq) = &entry.changefreq {
+            sitemap.push_str(&format!("    <changefreq>{}</changefreq>\n", changefreq));
+        }
+        if let Some(priority) = entry.priority {
+            sitemap.push_str(&format!(
+                "    <priority>{:.1}</priority>\n",
+                priority.clamp(0.0, 1.0)
+            ));
+        }
+        for image in &entry.images {
+            sitemap.push_str(&format!(
+                "    <image:image>\n      <image:loc>{}</image:loc>\n    </image:image>\n",
+                html_text(image)
+            ));
+        }
+        sitemap.push_str("  </url>\n");
     }
 
-    // Blog posts
-    for post in posts {
-        let post_url = post.file_name.replace("docs", "");
-        sitemap.push_str(&format!(
-            "  <url>\n    <loc>{}{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
-            base_url,
-            post_url,
-            chrono::Utc::now().format("%Y-%m-%d")
+    // Close XML
+    sitemap.push_str("</urlset>");
+    sitemap
+}
+
+/// Generate `sitemap.xml`
+///
+/// Sites with more than `MAX_SITEMAP_URLS` URLs get `sitemap-1.xml`,
+/// `sitemap-2.xml`, ... with `sitemap.xml` as the index pointing at them.
+fn generate_sitemap(entries: &[SitemapEntry], ctx: &BuildContext) {
+    let config = &ctx.config;
+    if entries.len() <= MAX_SITEMAP_URLS {
+        // Write to docs/sitemap.xml
+        ctx.write_output("sitemap.xml", &render_urlset(entries, config));
+        return;
+    }
+
+    let mut index = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+
+    for (i, chunk) in entries.chunks(MAX_SITEMAP_URLS).enumerate() {
+        let file_name = format!("sitemap-{}.xml", i + 1);
+        ctx.write_output(&file_name, &render_urlset(chunk, config));
+
+        index.push_str(&format!(
+            "  <sitemap>\n    <loc>{}</loc>\n",
+            html_text(&absolute_url(&config.site.base_url, &file_name))
         ));
+        if let Some(lastmod) = chunk.iter().filter_map(|e| e.lastmod.as_deref()).max() {
+            index.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod));
+        }
+        index.push_str("  </sitemap>\n");
     }
 
-    // Close XML
-    sitemap.push_str("</urlset>");
+    index.push_str("</sitemapindex>");
+
+    ctx.write_output("sitemap.xml", &index);
+}
+
+/// Generate `robots.txt` pointing crawlers at the sitemap
+fn generate_robots(ctx: &BuildContext) {
+    let config = &ctx.config;
+    let mut robots = String::from("User-agent: *\n");
+
+    if config.robots.disallow.is_empty() {
+        robots.push_str("Allow: /\n");
+    } else {
+        for path in &config.robots.disallow {
+            robots.push_str(&format!("Disallow: {}\n", path.trim()));
+        }
+    }
+
+    robots.push_str(&format!(
+        "\nSitemap: {}\n",
+        absolute_url(&config.site.base_url, "sitemap.xml")
+    ));
+
+    ctx.write_output("robots.txt", &robots);
+}
+
+/// Generate `humans.txt` crediting the author (see https://humanstxt.org)
+///
+/// "Last update" is the newest post date, so unchanged sites produce identical files.
+fn generate_humans(posts: &[Post], ctx: &BuildContext) {
+    let config = &ctx.config;
+    let mut humans = format!(
+        "/* TEAM */\n\tAuthor: {author}\n\tGitHub: {github}\n\tTwitter: {twitter}\n",
+        author = config.site.author,
+        github = config.links.github,
+        twitter = config.links.twitter,
+    );
+
+    if !config.humans.thanks.is_empty() {
+        humans.push_str("\n/* THANKS */\n");
+        for name in &config.humans.thanks {
+            humans.push_str(&format!("\tName: {}\n", name));
+        }
+    }
+
+    humans.push_str("\n/* SITE */\n");
+    let last_update = posts
+        .iter()
+        .filter_map(|post| {
+            let front_matter = &post.front_matter;
+            sitemap_date(
+                front_matter
+                    .updated
+                    .as_deref()
+                    .unwrap_or(&front_matter.date),
+            )
+        })
+        .max();
+    if let Some(last_update) = last_update {
+        humans.push_str(&format!(
+            "\tLast update: {}\n",
+            last_update.replace('-', "/")
+        ));
+    }
+    humans
+        .push_str("\tLanguage: English\n\tSoftware: Xeniria — https://github.com/0xh4ty/xeniria\n");
+
+    ctx.write_output("humans.txt", &humans);
+}
+
+/// Generate `.well-known/security.txt` (RFC 9116) from the `[security]` config
+///
+/// `Expires` is required by the RFC and checked at the start of the build; we
+/// warn if the configured value is invalid or in the past.
+fn generate_security_txt(ctx: &BuildContext) {
+    let config = &ctx.config;
+    let security = &config.security;
+    let mut body = String::new();
+
+    for contact in &security.contact {
+        let contact = contact.trim();
+        if contact.contains(':') {
+            body.push_str(&format!("Contact: {}\n", contact));
+        } else {
+            body.push_str(&format!("Contact: mailto:{}\n", contact));
+        }
+    }
+
+    let expires = security
+        .expires
+        .as_deref()
+        .expect("security.expires is checked at the start of the build");
+    match chrono::DateTime::parse_from_rfc3339(expires) {
+        Ok(date) if date < chrono::Utc::now() => {
+            println!("Warning: security.expires ({}) is in the past", expires)
+        }
+        Ok(_) => {}
+        Err(e) => println!(
+            "Warning: security.expires ({}) is not an RFC 3339 timestamp: {}",
+            expires, e
+        ),
+    }
+    body.push_str(&format!("Expires: {}\n", expires));
+
+    let optional_fields = [
+        ("Encryption", &security.encryption),
+        ("Acknowledgments", &security.acknowledgments),
+        ("Policy", &security.policy),
+        ("Hiring", &security.hiring),
+        ("Preferred-Languages", &security.preferred_languages),
+    ];
+    for (field, value) in optional_fields {
+        if let Some(value) = value {
+            body.push_str(&format!("{}: {}\n", field, value.trim()));
+        }
+    }
 
-    // Write to docs/sitemap.xml
-    let mut file = fs::File::create("docs/sitemap.xml").expect("Failed to create sitemap.xml");
-    file.write_all(sitemap.as_bytes())
-        .expect("Failed to write sitemap.xml");
+    body.push_str(&format!(
+        "Canonical: {}\n",
+        absolute_url(&config.site.base_url, ".well-known/security.txt")
+    ));
 
-    println!("Generated: docs/sitemap.xml");
+    ctx.write_output(".well-known/security.txt", &body);
 }
@@ -1,13 +1,23 @@
-use pulldown_cmark::{html, Options, Parser};
+use image::GenericImageView;
+use image::ImageReader;
+use percent_encoding::percent_decode_str;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
 use serde::Deserialize;
 use slug::slugify;
+use std::collections::HashSet;
 use std::fs;
-use regex::Regex;
-use image::ImageReader;
-use reqwest;
-use image::GenericImageView;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use crate::alerts;
+use crate::code_blocks;
+use crate::diagrams;
+use crate::escape::{html_attr, html_text, url_attr};
+use crate::footnotes;
+use crate::math;
+use crate::shortcodes;
+use crate::{canonical_path, BuildContext, MarkdownLinks};
+
 /// Front matter for a typical blog post (includes date).
 #[derive(Clone, Debug, Deserialize)]
 pub struct PostFrontMatter {
@@ -15,6 +25,24 @@ pub struct PostFrontMatter {
     pub date: String,
     pub author: String,
     pub description: Option<String>,
+    /// Date of the last significant edit, if different from `date`.
+    pub updated: Option<String>,
+    /// Preview image overriding the generated Open Graph card
+    /// (a path relative to `docs/`, or an absolute URL).
+    pub image: Option<String>,
+    /// Keep the post out of `sitemap.xml` and ask crawlers not to index it.
+    #[serde(default)]
+    pub noindex: bool,
+    /// Sitemap `<changefreq>` hint (e.g. "monthly").
+    pub changefreq: Option<String>,
+    /// Sitemap `<priority>` hint between 0.0 and 1.0.
+    pub priority: Option<f32>,
+    /// Drafts are left out of the build.
+    #[serde(default)]
+    pub draft: bool,
+    /// Render `$...$` and `$$...$$` as math.
+    #[serde(default)]
+    pub math: bool,
 }
 
 /// Front matter for a generic page (like About).
@@ -23,6 +51,23 @@ pub struct PageFrontMatter {
     pub title: String,
     pub author: String,
     pub description: Option<String>,
+    /// Publication date, used as the sitemap `<lastmod>` when set.
+    pub date: Option<String>,
+    /// Date of the last significant edit, preferred over `date`.
+    pub updated: Option<String>,
+    /// Keep the page out of `sitemap.xml` and ask crawlers not to index it.
+    #[serde(default)]
+    pub noindex: bool,
+    /// Sitemap `<changefreq>` hint (e.g. "yearly").
+    pub changefreq: Option<String>,
+    /// Sitemap `<priority>` hint between 0.0 and 1.0.
+    pub priority: Option<f32>,
+    /// Drafts are left out of the build.
+    #[serde(default)]
+    pub draft: bool,
+    /// Render `$...$` and `$$...$$` as math.
+    #[serde(default)]
+    pub math: bool,
 }
 
 /// Represents a single blog post.
@@ -34,8 +79,16 @@ pub struct Post {
     pub content: String,
     /// Estimated reading time (in minutes).
     pub reading_time: usize,
+    /// Number of words in the Markdown body.
+    pub word_count: usize,
+    /// `src` of every image kept in the content, as written in the Markdown.
+    pub images: Vec<String>,
+    /// Files pulled into code blocks with `file=`, which the post depends on.
+    pub includes: Vec<PathBuf>,
     /// Destination file name (e.g. "docs/posts/my-title.html").
     pub file_name: String,
+    /// Site-relative URL (e.g. "/posts/my-title.html").
+    pub url: String,
 }
 
 /// Represents a generic page (e.g., About page).
@@ -45,6 +98,10 @@ pub struct Page {
     pub front_matter: PageFrontMatter,
     /// Final HTML content after Markdown conversion.
     pub content: String,
+    /// `src` of every image kept in the content, as written in the Markdown.
+    pub images: Vec<String>,
+    /// Files pulled into code blocks with `file=`, which the page depends on.
+    pub includes: Vec<PathBuf>,
 }
 
 /// Parses a blog post Markdown file with front matter:
@@ -54,107 +111,53 @@ pub struct Page {
 /// title: "My Post"
 /// date: "2025-01-30"
 /// author: "John Doe"
+/// updated: "2025-02-14" # optional
+/// image: "assets/img/cover.png" # optional
 /// ---
 ///
 /// # My Post Content
 /// ```
-
-pub fn parse_post_markdown(file_path: &str) -> Result<Post, Box<dyn std::error::Error>> {
+///
+/// The post is destined for `<output_dir>/posts/` and local images are looked
+/// up beneath the output directory.
+pub fn parse_post_markdown(
+    file_path: &str,
+    ctx: &BuildContext,
+) -> Result<Post, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(file_path)?;
 
-    // 1. Split off the leading '---\n'
-    let mut sections = content.splitn(2, "---\n");
-    sections.next(); // skip the first empty part if any
-
-    // 2. Extract front matter + remainder
-    let front_matter_str = sections
-        .next()
-        .ok_or("Missing front matter section (--- line not found)")?;
-
-    // 3. Split front matter from the actual Markdown body
-    let mut body_sections = front_matter_str.splitn(2, "\n---\n");
-    let front_matter_yaml = body_sections
-        .next()
-        .ok_or("Missing YAML front matter contents")?;
-    let markdown_body = body_sections
-        .next()
-        .ok_or("Missing Markdown body after front matter")?;
+    // 1-3. Split the YAML front matter from the Markdown body
+    let (front_matter_yaml, markdown_body) = split_front_matter(&content)?;
 
     // 4. Parse front matter with Serde
     let front_matter: PostFrontMatter = serde_yaml::from_str(front_matter_yaml)?;
 
     // 5. Convert Markdown to HTML
-    let mut html_output = String::new();
-    let parser = Parser::new_ext(markdown_body, Options::all());
-    html::push_html(&mut html_output, parser);
-
-    let html_output = html_output.replace(
-        "<pre><code class=\"language-",
-        "<pre class=\"line-numbers\"><code class=\"language-",
-    );
+    let rendered = render_markdown(markdown_body, file_path, front_matter.math, ctx)?;
 
     // 6. Calculate estimated reading time (assume ~200 words/min)
     let word_count = markdown_body.split_whitespace().count();
     let reading_time = (word_count as f64 / 200.0).ceil() as usize;
 
-    // 7. Generate a default file name in `docs/posts`
+    // 7. Generate a default file name in `<output_dir>/posts`
     let slug = slugify(&front_matter.title);
-    let file_name = format!("docs/posts/{}.html", slug);
-
-    // after you generate html_output
-    let img_tag_re = Regex::new(r#"<img\s+[^>]*src="([^"]+)"\s+alt="([^"]*)".*?/?>"#)?;
-
-    let rewritten_html = img_tag_re.replace_all(&html_output, |caps: &regex::Captures| {
-        let src = &caps[1];
-        let alt = &caps[2];
-
-        if src.starts_with("http") {
-            let client = reqwest::blocking::Client::builder()
-                .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-                .timeout(Duration::from_secs(10))
-                .build()
-                .unwrap();
-
-            match client.get(src).send() {
-                Ok(response) if response.status().is_success() => {
-                    if let Ok(bytes) = response.bytes() {
-                        if let Ok(img) = image::load_from_memory(&bytes) {
-                            let dims = img.dimensions();
-                            return format!(
-                                r#"<div class='shimmer aspect-ratio' style='--aspect-ratio:{} / {}'><img src="{}" alt="{}"/></div>"#,
-                                dims.0, dims.1, src, alt
-                            );
-                        }
-                    }
-                }
-                _ => {}
-            }
-
-            println!("Could not retrieve or decode remote image '{}', continuing without image", src);
-            return "".to_string();
-        }
-
-        // local
-        let cleaned_src = src.trim_start_matches("../");
-        let src_path = std::path::Path::new("docs").join(cleaned_src);
-
-        if let Ok(img) = ImageReader::open(&src_path) {
-            let dims = img.into_dimensions().unwrap_or((0, 0));
-            return format!(
-                r#"<div class='shimmer aspect-ratio' style='--aspect-ratio:{} / {}'><img src="{}" alt="{}"/></div>"#,
-                dims.0, dims.1, src, alt
-            );
-        }
-
-        println!("Could not open local image '{}', continuing without image", src);
-        "".to_string()
-    }).to_string();
+    let url = post_url(&front_matter);
+    let file_name = ctx
+        .output_dir
+        .join("posts")
+        .join(format!("{}.html", slug))
+        .to_string_lossy()
+        .to_string();
 
     Ok(Post {
         front_matter,
-        content: rewritten_html,
+        content: rendered.html,
         reading_time,
+        word_count,
+        images: rendered.images,
+        includes: rendered.includes,
         file_name,
+        url,
     })
 }
 
@@ -168,19 +171,44 @@ pub fn parse_post_markdown(file_path: &str) -> Result<Post, Box<dyn std::error::
 ///
 /// # About Content Here
 /// ```
-pub fn parse_page_markdown(file_path: &str) -> Result<Page, Box<dyn std::error::Error>> {
+///
+/// Local images are looked up beneath the output directory.
+pub fn parse_page_markdown(
+    file_path: &str,
+    ctx: &BuildContext,
+) -> Result<Page, Box<dyn std::error::Error>> {
     let content = fs::read_to_string(file_path)?;
 
-    // 1. Split off the leading '---\n'
+    // 1-3. Split the YAML front matter from the Markdown body
+    let (front_matter_yaml, markdown_body) = split_front_matter(&content)?;
+
+    // 4. Parse front matter with Serde
+    let front_matter: PageFrontMatter = serde_yaml::from_str(front_matter_yaml)?;
+
+    // 5. Convert Markdown to HTML
+    let rendered = render_markdown(markdown_body, file_path, front_matter.math, ctx)?;
+
+    Ok(Page {
+        front_matter,
+        content: rendered.html,
+        images: rendered.images,
+        includes: rendered.includes,
+    })
+}
+
+/// Splits a Markdown file into its YAML front matter (between `---` lines)
+/// and body
+fn split_front_matter(content: &str) -> Result<(&str, &str), Box<dyn std::error::Error>> {
+    // Split off the leading '---\n'
     let mut sections = content.splitn(2, "---\n");
     sections.next(); // skip the first empty part if any
 
-    // 2. Extract front matter + remainder
+    // Extract front matter + remainder
     let front_matter_str = sections
         .next()
         .ok_or("Missing front matter section (--- line not found)")?;
 
-    // 3. Split front matter from the actual Markdown body
+    // Split front matter from the actual Markdown body
     let mut body_sections = front_matter_str.splitn(2, "\n---\n");
     let front_matter_yaml = body_sections
         .next()
@@ -188,71 +216,562 @@ pub fn parse_page_markdown(file_path: &str) -> Result<Page, Box<dyn std::error::
     let markdown_body = body_sections
         .next()
         .ok_or("Missing Markdown body after front matter")?;
+    Ok((front_matter_yaml, markdown_body))
+}
 
-    // 4. Parse front matter with Serde
-    let front_matter: PageFrontMatter = serde_yaml::from_str(front_matter_yaml)?;
+/// Reads just the front matter of a post, e.g. to learn its URL before it's
+/// rendered
+pub fn read_post_front_matter(
+    file_path: &str,
+) -> Result<PostFrontMatter, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(file_path)?;
+    let (front_matter_yaml, _) = split_front_matter(&content)?;
+    Ok(serde_yaml::from_str(front_matter_yaml)?)
+}
+
+/// Whether a content file declares `type: page` in its front matter, which
+/// builds it to `/<file name>.html` as a standalone page instead of a post
+pub fn is_page(file_path: &str) -> bool {
+    #[derive(Deserialize)]
+    struct ContentType {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+    }
+
+    let Ok(content) = fs::read_to_string(file_path) else {
+        return false;
+    };
+    split_front_matter(&content)
+        .ok()
+        .and_then(|(yaml, _)| serde_yaml::from_str::<ContentType>(yaml).ok())
+        .and_then(|front_matter| front_matter.kind)
+        .is_some_and(|kind| kind == "page")
+}
+
+/// Site-relative URL of a post, e.g. "/posts/my-title.html"
+pub fn post_url(front_matter: &PostFrontMatter) -> String {
+    format!("/posts/{}.html", slugify(&front_matter.title))
+}
+
+/// A post or page body rendered to HTML
+struct Rendered {
+    html: String,
+    /// `src` of every image kept in the HTML
+    images: Vec<String>,
+    /// Files included by code blocks
+    includes: Vec<PathBuf>,
+}
+
+/// Renders the body of the Markdown file `file_path` to HTML.
+///
+/// `math` turns on `$...$` and `$$...$$` (rendered to MathML); without it
+/// dollar signs are plain text. Local images are looked up beneath
+/// `ctx.output_dir`.
+fn render_markdown(
+    markdown_body: &str,
+    file_path: &str,
+    math: bool,
+    ctx: &BuildContext,
+) -> Result<Rendered, Box<dyn std::error::Error>> {
+    // Shortcodes become HTML (and Markdown) before the parser sees them
+    let markdown = shortcodes::expand(markdown_body, &ctx.root_dir)?;
+
+    // GFM footnotes: the old style ends a definition at the first blank line
+    let mut options = Options::all();
+    options.remove(Options::ENABLE_OLD_FOOTNOTES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    if !math {
+        options.remove(Options::ENABLE_MATH);
+    }
+
+    let events: Vec<Event> = Parser::new_ext(&markdown, options)
+        .map(|event| match event {
+            Event::InlineMath(tex) => Event::InlineHtml(render_math(&tex, false).into()),
+            Event::DisplayMath(tex) => Event::InlineHtml(render_math(&tex, true).into()),
+            event => event,
+        })
+        .collect();
+    let events = heading_ids(events);
+    let events = alerts::transform(events, &ctx.config.alerts)?;
+    let (events, includes) = code_blocks::transform(events, &ctx.source_dir)?;
+    let events = diagrams::transform(events, &ctx.root_dir);
+    let (events, images) = transform_images(events, ctx.output_dir.as_path());
+    let events = transform_links(events, file_path, ctx);
+    // Last, so sidenotes copy links and images that are already rewritten
+    let events = footnotes::transform(events, &ctx.config.footnotes);
 
-    // 5. Convert Markdown to HTML
     let mut html_output = String::new();
-    let parser = Parser::new_ext(markdown_body, Options::all());
-    html::push_html(&mut html_output, parser);
+    html::push_html(&mut html_output, events.into_iter());
 
     let html_output = html_output.replace(
         "<pre><code class=\"language-",
         "<pre class=\"line-numbers\"><code class=\"language-",
     );
 
-    // after you generate html_output
-    let img_tag_re = Regex::new(r#"<img\s+[^>]*src="([^"]+)"\s+alt="([^"]*)".*?/?>"#)?;
+    if let Some(url) = ctx.content_urls.get(&canonical_path(file_path)) {
+        ctx.cross_refs.record_anchors(url, &html_output);
+    }
 
-    let rewritten_html = img_tag_re.replace_all(&html_output, |caps: &regex::Captures| {
-        let src = &caps[1];
-        let alt = &caps[2];
+    Ok(Rendered {
+        html: html_output,
+        images,
+        includes,
+    })
+}
 
-        if src.starts_with("http") {
-            let client = reqwest::blocking::Client::builder()
-                .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-                .timeout(Duration::from_secs(10))
-                .build()
-                .unwrap();
-
-            match client.get(src).send() {
-                Ok(response) if response.status().is_success() => {
-                    if let Ok(bytes) = response.bytes() {
-                        if let Ok(img) = image::load_from_memory(&bytes) {
-                            let dims = img.dimensions();
-                            return format!(
-                                r#"<div class='shimmer aspect-ratio' style='--aspect-ratio:{} / {}'><img src="{}" alt="{}"/></div>"#,
-                                dims.0, dims.1, src, alt
-                            );
+/// Replaces Markdown images (inline or reference-style) with a shimmer
+/// placeholder sized to the image, returning the `src` of every image kept.
+///
+/// An image with a title becomes a `<figure>` captioned with it. An image
+/// alone in its paragraph (or alone inside a link there) is laid out as a
+/// block in place of the paragraph. Images that can't be fetched or opened
+/// (local ones are looked up beneath `output_dir`) are dropped.
+fn transform_images<'a>(
+    events: Vec<Event<'a>>,
+    output_dir: &Path,
+) -> (Vec<Event<'a>>, Vec<String>) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut images = Vec::new();
+    let mut i = 0;
+
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::Paragraph) => {
+                if let Some(lone) = lone_image(&events[i..]) {
+                    let image = &events[i + lone.image];
+                    if let Some(html) = render_image(
+                        image,
+                        &alt_text(&events[i + lone.image..]),
+                        true,
+                        output_dir,
+                    ) {
+                        images.push(image_src(image).to_string());
+                        let (open, close) = match image_title(image) {
+                            "" => (String::new(), String::new()),
+                            title => (
+                                "<figure class=\"figure\">\n".to_string(),
+                                format!(
+                                    "<figcaption>{}</figcaption>\n</figure>\n",
+                                    html_text(title)
+                                ),
+                            ),
+                        };
+                        if !open.is_empty() {
+                            output.push(Event::Html(CowStr::from(open)));
+                        }
+                        // Keep the link as an event so the link rewriting still applies
+                        if let Some(link) = lone.link {
+                            output.push(events[i + link].clone());
                         }
+                        output.push(Event::Html(CowStr::from(html)));
+                        if lone.link.is_some() {
+                            output.push(Event::End(TagEnd::Link));
+                        }
+                        output.push(Event::Html(CowStr::from(format!("\n{}", close))));
                     }
+                    i += lone.len;
+                    continue;
                 }
-                _ => {}
+                output.push(events[i].clone());
+            }
+            Event::Start(Tag::Image { .. }) => {
+                let image = &events[i];
+                if let Some(html) = render_image(image, &alt_text(&events[i..]), false, output_dir)
+                {
+                    images.push(image_src(image).to_string());
+                    output.push(Event::InlineHtml(CowStr::from(html)));
+                }
+                i += image_len(&events[i..]);
+                continue;
+            }
+            event => output.push(event.clone()),
+        }
+        i += 1;
+    }
+
+    (output, images)
+}
+
+/// Positions (from the paragraph start) of an image filling a paragraph on
+/// its own, and of the link around it if any
+struct LoneImage {
+    image: usize,
+    link: Option<usize>,
+    /// Events up to and including the end of the paragraph
+    len: usize,
+}
+
+fn lone_image(paragraph: &[Event]) -> Option<LoneImage> {
+    let mut image = None;
+    let mut link = None;
+    let mut i = 1;
+    while i < paragraph.len() {
+        match &paragraph[i] {
+            Event::End(TagEnd::Paragraph) => {
+                return image.map(|image| LoneImage {
+                    image,
+                    link,
+                    len: i + 1,
+                });
+            }
+            Event::Text(text) if text.trim().is_empty() => {}
+            Event::SoftBreak => {}
+            Event::Start(Tag::Link { .. }) if image.is_none() && link.is_none() && i == 1 => {
+                link = Some(i);
+            }
+            Event::End(TagEnd::Link) if link.is_some() && image.is_some() => {}
+            Event::Start(Tag::Image { .. }) if image.is_none() => {
+                image = Some(i);
+                i += image_len(&paragraph[i..]);
+                continue;
             }
+            _ => return None,
+        }
+        i += 1;
+    }
+    None
+}
 
-            println!("Could not retrieve or decode remote image '{}', continuing without image", src);
-            return "".to_string();
+/// Number of events from an image's start to its end, inclusive
+fn image_len(events: &[Event]) -> usize {
+    let mut depth = 0;
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Image { .. }) => depth += 1,
+            Event::End(TagEnd::Image) => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
         }
+    }
+    events.len()
+}
 
-        // local
-        let cleaned_src = src.trim_start_matches("../");
-        let src_path = std::path::Path::new("docs").join(cleaned_src);
+/// Plain text of an image's description, used as its `alt`
+fn alt_text(events: &[Event]) -> String {
+    let mut alt = String::new();
+    for event in &events[1..image_len(events) - 1] {
+        match event {
+            Event::Text(text) | Event::Code(text) | Event::InlineHtml(text) => alt.push_str(text),
+            Event::SoftBreak | Event::HardBreak => alt.push(' '),
+            _ => {}
+        }
+    }
+    alt
+}
+
+fn image_src<'e>(image: &'e Event) -> &'e str {
+    match image {
+        Event::Start(Tag::Image { dest_url, .. }) => dest_url,
+        _ => "",
+    }
+}
+
+fn image_title<'e>(image: &'e Event) -> &'e str {
+    match image {
+        Event::Start(Tag::Image { title, .. }) => title,
+        _ => "",
+    }
+}
 
-        if let Ok(img) = ImageReader::open(&src_path) {
-            let dims = img.into_dimensions().unwrap_or((0, 0));
-            return format!(
-                r#"<div class='shimmer aspect-ratio' style='--aspect-ratio:{} / {}'><img src="{}" alt="{}"/></div>"#,
-                dims.0, dims.1, src, alt
+/// Placeholder sized to the image with the `<img>` inside, or `None` if the
+/// image can't be fetched or opened. `block` picks a `<div>` over a `<span>`,
+/// which can sit inside a paragraph.
+fn render_image(image: &Event, alt: &str, block: bool, output_dir: &Path) -> Option<String> {
+    let src = image_src(image);
+    let Some((width, height)) = image_dimensions(src, output_dir) else {
+        if src.starts_with("http") {
+            println!(
+                "Could not retrieve or decode remote image '{}', continuing without image",
+                src
+            );
+        } else {
+            println!(
+                "Could not open local image '{}', continuing without image",
+                src
             );
         }
+        return None;
+    };
+
+    let element = if block { "div" } else { "span" };
+    // A figure's caption already shows the title
+    let title = match image_title(image) {
+        title if !title.is_empty() && !block => format!(" title=\"{}\"", html_attr(title)),
+        _ => String::new(),
+    };
+    Some(format!(
+        r#"<{element} class='shimmer aspect-ratio' style='--aspect-ratio:{width} / {height}'><img src="{src}" alt="{alt}"{title}/></{element}>"#,
+        element = element,
+        width = width,
+        height = height,
+        src = url_attr(src),
+        alt = html_attr(alt),
+        title = title,
+    ))
+}
 
-        println!("Could not open local image '{}', continuing without image", src);
-        "".to_string()
-    }).to_string();
+/// Pixel size of a remote image, or of a local one beneath `output_dir`
+fn image_dimensions(src: &str, output_dir: &Path) -> Option<(u32, u32)> {
+    if src.starts_with("http") {
+        let client = reqwest::blocking::Client::builder()
+            .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+            .timeout(Duration::from_secs(10))
+            .build()
+            .ok()?;
+        let response = client.get(src).send().ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        let bytes = response.bytes().ok()?;
+        return image::load_from_memory(&bytes)
+            .ok()
+            .map(|img| img.dimensions());
+    }
+
+    let cleaned_src = src.trim_start_matches("../");
+    let img = ImageReader::open(output_dir.join(cleaned_src)).ok()?;
+    Some(img.into_dimensions().unwrap_or((0, 0)))
+}
 
-    Ok(Page {
-        front_matter,
-        content: rewritten_html,
-    })
+/// Gives every heading without an explicit `{#id}` one made from its text,
+/// so sections can be linked to
+fn heading_ids(events: Vec<Event>) -> Vec<Event> {
+    // Explicit ids are claimed first so generated ones steer clear of them
+    let mut used: HashSet<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut output = Vec::with_capacity(events.len());
+
+    for (i, event) in events.iter().enumerate() {
+        let Event::Start(Tag::Heading {
+            level,
+            id: None,
+            classes,
+            attrs,
+        }) = event
+        else {
+            output.push(event.clone());
+            continue;
+        };
+
+        let mut text = String::new();
+        for event in &events[i + 1..] {
+            match event {
+                Event::Text(t) | Event::Code(t) => text.push_str(t),
+                Event::End(TagEnd::Heading(_)) => break,
+                _ => {}
+            }
+        }
+        let mut base = slugify(&text);
+        if base.is_empty() {
+            base = "section".to_string();
+        }
+        let mut id = base.clone();
+        let mut n = 2;
+        while used.contains(&id) {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        used.insert(id.clone());
+
+        output.push(Event::Start(Tag::Heading {
+            level: *level,
+            id: Some(CowStr::from(id)),
+            classes: classes.clone(),
+            attrs: attrs.clone(),
+        }));
+    }
+
+    output
+}
+
+/// Applies `[markdown_links]`: links to other sites get `rel`, `target` and
+/// the `external-link` class, and relative links to `.md` files are pointed
+/// at the pages built from them. `@/` cross-references are always resolved.
+fn transform_links<'a>(
+    events: Vec<Event<'a>>,
+    file_path: &str,
+    ctx: &BuildContext,
+) -> Vec<Event<'a>> {
+    let options = &ctx.config.markdown_links;
+    let base_url = ctx.config.site.base_url.trim_end_matches('/');
+    let mut output = Vec::with_capacity(events.len());
+    // Closing markup for each open link, if it was replaced with raw HTML
+    let mut open: Vec<Option<&'static str>> = Vec::new();
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                if dest_url.starts_with("@/") {
+                    let dest_url = ctx
+                        .cross_refs
+                        .resolve(&dest_url, file_path, &ctx.source_dir, &ctx.content_urls)
+                        .map(|url| CowStr::from(relative_url(&url, file_path, ctx)))
+                        .unwrap_or(dest_url);
+                    output.push(Event::Start(Tag::Link {
+                        link_type,
+                        dest_url,
+                        title,
+                        id,
+                    }));
+                    open.push(None);
+                    continue;
+                }
+                if is_external(&dest_url, base_url) {
+                    let (tag, close) = external_link(&dest_url, &title, options);
+                    output.push(Event::InlineHtml(CowStr::from(tag)));
+                    open.push(Some(close));
+                    continue;
+                }
+                let dest_url = if options.rewrite_md {
+                    rewrite_md_link(dest_url, file_path, ctx)
+                } else {
+                    dest_url
+                };
+                output.push(Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }));
+                open.push(None);
+            }
+            Event::End(TagEnd::Link) => match open.pop().flatten() {
+                Some(close) => output.push(Event::InlineHtml(CowStr::from(close))),
+                None => output.push(Event::End(TagEnd::Link)),
+            },
+            event => output.push(event),
+        }
+    }
+
+    output
+}
+
+/// Links to another site: absolute or protocol-relative, and not under `base_url`
+fn is_external(url: &str, base_url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    let absolute =
+        lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("//");
+    absolute && !under_base_url(url, base_url)
+}
+
+/// Whether `url` is `base_url` or below it. The scheme and host are compared
+/// case-insensitively, like browsers do, and the path exactly
+fn under_base_url(url: &str, base_url: &str) -> bool {
+    let base = base_url.trim_end_matches('/');
+    if base.is_empty() {
+        return false;
+    }
+    let authority_start = base.find("://").map_or(0, |i| i + 3);
+    let path_start = base[authority_start..]
+        .find('/')
+        .map_or(base.len(), |i| authority_start + i);
+    let Some(head) = url.get(..path_start) else {
+        return false;
+    };
+    if !head.eq_ignore_ascii_case(&base[..path_start]) {
+        return false;
+    }
+    match url[path_start..].strip_prefix(&base[path_start..]) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['/', '#', '?']),
+        None => false,
+    }
+}
+
+/// Opening tag for a link to another site, and the markup closing it
+fn external_link(url: &str, title: &str, options: &MarkdownLinks) -> (String, &'static str) {
+    let mut tag = format!("<a href=\"{}\"", url_attr(url));
+    if !title.is_empty() {
+        tag.push_str(&format!(" title=\"{}\"", html_attr(title)));
+    }
+    if options.icon {
+        tag.push_str(" class=\"external-link\"");
+    }
+    if options.new_tab {
+        tag.push_str(" target=\"_blank\"");
+    }
+    if !options.rel.is_empty() {
+        tag.push_str(&format!(" rel=\"{}\"", html_attr(&options.rel)));
+    }
+    tag.push('>');
+
+    let close = if options.new_tab {
+        "<span class=\"visually-hidden\"> (opens in a new tab)</span></a>"
+    } else {
+        "</a>"
+    };
+    (tag, close)
+}
+
+/// Points a relative link to a `.md` file (with an optional `#fragment`) at
+/// the page built from it, relative to the page built from `file_path`
+fn rewrite_md_link<'a>(url: CowStr<'a>, file_path: &str, ctx: &BuildContext) -> CowStr<'a> {
+    let has_scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.contains('/'));
+    if url.is_empty() || url.starts_with('/') || url.starts_with('#') || has_scheme {
+        return url;
+    }
+    let split = url.find(['#', '?']).unwrap_or(url.len());
+    let (path, rest) = url.split_at(split);
+    if !path.ends_with(".md") {
+        return url;
+    }
+
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let target = Path::new(file_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(path.as_ref());
+    let Some(target_url) = ctx.content_urls.get(&canonical_path(&target)) else {
+        println!(
+            "Link to {} in {} doesn't match a post or page, leaving it as is",
+            url, file_path
+        );
+        return url;
+    };
+
+    CowStr::from(format!(
+        "{}{}",
+        relative_url(target_url, file_path, ctx),
+        rest
+    ))
+}
+
+/// `url` (site-relative) as linked from the page built from `file_path`
+fn relative_url(url: &str, file_path: &str, ctx: &BuildContext) -> String {
+    // Relative, like the rest of the site's links; the 404 page is served at
+    // any depth so it gets absolute ones
+    let prefix = match ctx.content_urls.get(&canonical_path(file_path)) {
+        Some(own_url) => "../".repeat(own_url.matches('/').count().saturating_sub(1)),
+        None => format!("{}/", ctx.config.site.base_url.trim_end_matches('/')),
+    };
+    format!("{}{}", prefix, url.trim_start_matches('/'))
+}
+
+/// MathML for a formula, or its source if it can't be converted
+fn render_math(tex: &str, display: bool) -> String {
+    match math::to_mathml(tex, display) {
+        Ok(mathml) => mathml,
+        Err(e) => {
+            println!(
+                "Could not render math '{}': {}, showing the TeX source",
+                tex, e
+            );
+            format!("<code class=\"math-error\">{}</code>", html_text(tex))
+        }
+    }
 }
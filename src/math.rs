@@ -0,0 +1,922 @@
+//! TeX math to MathML, so formulas render without client-side JavaScript.
+//!
+//! Posts and pages opt in with `math: true` in their front matter; `$...$`
+//! is then inline math and `$$...$$` display math. Everywhere else a dollar
+//! sign is just a dollar sign.
+//!
+//! This covers the everyday subset of LaTeX math: scripts, fractions, roots,
+//! Greek letters and common symbols, big operators with limits, accents,
+//! `\text`, font commands, `\left`/`\right` and the matrix, `cases` and
+//! `aligned` environments. The TeX source is kept in an `<annotation>` for
+//! copying and assistive technology.
+
+use crate::escape::{html_attr, html_text};
+
+/// Renders `tex` as a `<math>` element, or describes what couldn't be parsed.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+        display,
+    };
+    let body = parser.row()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected '{}'", c));
+    }
+
+    Ok(format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"{}\"><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { "block" } else { "inline" },
+        mrow(body),
+        html_text(tex.trim()),
+    ))
+}
+
+/// A parsed piece of a formula
+struct Node {
+    markup: String,
+    /// Scripts go above/below (`\sum`, `\lim`) in display math
+    limits: bool,
+}
+
+impl Node {
+    fn new(markup: String) -> Node {
+        Node {
+            markup,
+            limits: false,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}'", expected)),
+        }
+    }
+
+    /// Whether the input continues with `\name` (not followed by more letters)
+    fn at_command(&self, name: &str) -> bool {
+        let end = self.pos + 1 + name.chars().count();
+        self.peek() == Some('\\')
+            && self
+                .chars
+                .get(self.pos + 1..end)
+                .is_some_and(|s| s.iter().copied().eq(name.chars()))
+            && !self.chars.get(end).is_some_and(|c| c.is_ascii_alphabetic())
+    }
+
+    /// Parses nodes up to the end of the current group, row or cell.
+    fn row(&mut self) -> Result<Vec<String>, String> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some('}') | Some('&') => break,
+                Some('\\')
+                    if self.chars.get(self.pos + 1) == Some(&'\\')
+                        || self.at_command("right")
+                        || self.at_command("end") =>
+                {
+                    break
+                }
+                Some('^') | Some('_') => {
+                    // A script with nothing before it attaches to an empty base
+                    let scripted = self.scripts(Node::new("<mrow></mrow>".to_string()))?;
+                    nodes.push(scripted);
+                }
+                _ => {
+                    let Some(atom) = self.atom()? else {
+                        continue;
+                    };
+                    let scripted = self.scripts(atom)?;
+                    nodes.push(scripted);
+                }
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// Attaches any `^`, `_` and `'` following `base`.
+    fn scripts(&mut self, base: Node) -> Result<String, String> {
+        let mut sub = None;
+        let mut sup = None;
+        let mut primes = 0;
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.argument()?);
+                }
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.argument()?);
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    primes += 1;
+                }
+                Some('^') | Some('_') => return Err("double superscript or subscript".to_string()),
+                _ => break,
+            }
+        }
+
+        if primes > 0 {
+            let prime = format!("<mo>{}</mo>", "′".repeat(primes));
+            sup = Some(match sup {
+                Some(sup) => format!("<mrow>{}{}</mrow>", prime, sup),
+                None => prime,
+            });
+        }
+
+        let under_over = base.limits && self.display;
+        Ok(match (sub, sup) {
+            (None, None) => base.markup,
+            (Some(sub), None) if under_over => format!("<munder>{}{}</munder>", base.markup, sub),
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base.markup, sub),
+            (None, Some(sup)) if under_over => format!("<mover>{}{}</mover>", base.markup, sup),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base.markup, sup),
+            (Some(sub), Some(sup)) if under_over => {
+                format!("<munderover>{}{}{}</munderover>", base.markup, sub, sup)
+            }
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base.markup, sub, sup),
+        })
+    }
+
+    /// A single-atom argument: `{...}`, one character or one command.
+    fn argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err("missing argument".to_string()),
+            Some('{') => self.group(),
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(format!("<mn>{}</mn>", c))
+            }
+            _ => match self.atom()? {
+                Some(atom) => Ok(atom.markup),
+                None => Err("missing argument".to_string()),
+            },
+        }
+    }
+
+    /// `{...}` as one `<mrow>`
+    fn group(&mut self) -> Result<String, String> {
+        self.expect('{')?;
+        let nodes = self.row()?;
+        self.expect('}')?;
+        Ok(mrow(nodes))
+    }
+
+    /// Raw text of a `{...}` argument, for `\text` and friends
+    fn text_argument(&mut self) -> Result<String, String> {
+        self.expect('{')?;
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(text),
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err("unclosed '{'".to_string())
+    }
+
+    /// Parses one atom; `None` for things that produce no output (e.g. `\displaystyle`).
+    fn atom(&mut self) -> Result<Option<Node>, String> {
+        self.skip_whitespace();
+        let Some(c) = self.peek() else {
+            return Err("unexpected end of formula".to_string());
+        };
+
+        let markup = match c {
+            '{' => self.group()?,
+            '\\' => return self.command(),
+            c if c.is_ascii_digit()
+                || (c == '.'
+                    && self
+                        .chars
+                        .get(self.pos + 1)
+                        .is_some_and(char::is_ascii_digit)) =>
+            {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                format!("<mn>{}</mn>", number)
+            }
+            c if c.is_alphabetic() => {
+                self.pos += 1;
+                format!("<mi>{}</mi>", html_text(&c.to_string()))
+            }
+            '~' => {
+                self.pos += 1;
+                "<mspace width=\"0.3333em\"></mspace>".to_string()
+            }
+            '(' | ')' | '[' | ']' | '|' => {
+                self.pos += 1;
+                format!("<mo stretchy=\"false\">{}</mo>", c)
+            }
+            _ => {
+                self.pos += 1;
+                let symbol = match c {
+                    '-' => '−',
+                    '*' => '∗',
+                    c => c,
+                };
+                format!("<mo>{}</mo>", html_text(&symbol.to_string()))
+            }
+        };
+        Ok(Some(Node::new(markup)))
+    }
+
+    /// Parses `\name` and whatever arguments it takes.
+    fn command(&mut self) -> Result<Option<Node>, String> {
+        self.pos += 1; // the backslash
+        let Some(first) = self.peek() else {
+            return Err("formula ends with '\\'".to_string());
+        };
+        self.pos += 1;
+
+        // Single-character commands: escapes and spacing
+        if !first.is_ascii_alphabetic() {
+            let markup = match first {
+                ',' => space("0.1667em"),
+                ':' | '>' => space("0.2222em"),
+                ';' => space("0.2778em"),
+                '!' => space("-0.1667em"),
+                ' ' => space("0.3333em"),
+                '{' | '}' | '|' => format!("<mo>{}</mo>", if first == '|' { '‖' } else { first }),
+                '%' | '$' | '#' | '&' | '_' => {
+                    format!("<mi>{}</mi>", html_text(&first.to_string()))
+                }
+                c => return Err(format!("unknown command '\\{}'", c)),
+            };
+            return Ok(Some(Node::new(markup)));
+        }
+
+        let start = self.pos - 1;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+
+        if let Some(symbol) = identifier(&name) {
+            return Ok(Some(Node::new(format!("<mi>{}</mi>", symbol))));
+        }
+        if let Some(symbol) = operator(&name) {
+            return Ok(Some(Node::new(format!("<mo>{}</mo>", symbol))));
+        }
+        if let Some((symbol, limits)) = large_operator(&name) {
+            return Ok(Some(Node {
+                markup: format!(
+                    "<mo largeop=\"true\" movablelimits=\"true\">{}</mo>",
+                    symbol
+                ),
+                limits,
+            }));
+        }
+        if let Some(limits) = function(&name) {
+            return Ok(Some(Node {
+                markup: format!("<mi mathvariant=\"normal\">{}</mi>", name),
+                limits,
+            }));
+        }
+        if let Some((accent, stretchy)) = accent(&name) {
+            let base = self.argument()?;
+            return Ok(Some(Node::new(format!(
+                "<mover accent=\"true\">{}<mo stretchy=\"{}\">{}</mo></mover>",
+                base, stretchy, accent
+            ))));
+        }
+
+        let markup = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let n = self.argument()?;
+                let k = self.argument()?;
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    n, k
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let mut index = Vec::new();
+                    loop {
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(']') => break,
+                            None => return Err("unclosed '[' in \\sqrt".to_string()),
+                            _ => {
+                                if let Some(atom) = self.atom()? {
+                                    index.push(self.scripts(atom)?);
+                                }
+                            }
+                        }
+                    }
+                    self.pos += 1;
+                    let radicand = self.argument()?;
+                    format!("<mroot>{}{}</mroot>", radicand, mrow(index))
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.argument()?)
+                }
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+                let text = self.text_argument()?;
+                format!("<mtext>{}</mtext>", html_text(&text))
+            }
+            "mathrm" | "operatorname" => {
+                let text = self.text_argument()?;
+                format!("<mi mathvariant=\"normal\">{}</mi>", html_text(text.trim()))
+            }
+            "mathbb" | "mathbf" | "mathcal" | "mathit" | "mathsf" | "mathtt" | "boldsymbol" => {
+                let text = self.text_argument()?;
+                styled(&name, text.trim())
+            }
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "left" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => {
+                let open = self.delimiter()?;
+                if name != "left" {
+                    format!("<mo>{}</mo>", open)
+                } else {
+                    let inner = self.row()?;
+                    if !self.at_command("right") {
+                        return Err("\\left without \\right".to_string());
+                    }
+                    self.pos += "\\right".len();
+                    let close = self.delimiter()?;
+                    format!(
+                        "<mrow><mo fence=\"true\">{}</mo>{}<mo fence=\"true\">{}</mo></mrow>",
+                        open,
+                        inner.concat(),
+                        close
+                    )
+                }
+            }
+            "not" => {
+                let Some(next) = self.atom()? else {
+                    return Err("\\not without a symbol".to_string());
+                };
+                // Combining long solidus overlay
+                next.markup.replacen("</mo>", "\u{338}</mo>", 1)
+            }
+            "pmod" => {
+                let argument = self.argument()?;
+                format!(
+                    "<mrow><mspace width=\"1em\"></mspace><mo>(</mo><mi mathvariant=\"normal\">mod</mi><mspace width=\"0.3333em\"></mspace>{}<mo>)</mo></mrow>",
+                    argument
+                )
+            }
+            "bmod" | "mod" => "<mo lspace=\"0.2222em\" rspace=\"0.2222em\">mod</mo>".to_string(),
+            "begin" => self.environment()?,
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => return Ok(None),
+            _ => return Err(format!("unknown command '\\{}'", name)),
+        };
+        Ok(Some(Node::new(markup)))
+    }
+
+    /// Delimiter after `\left`, `\right` or `\big`; `.` is an empty one
+    fn delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('.') => {
+                self.pos += 1;
+                Ok(String::new())
+            }
+            Some('\\') => {
+                self.pos += 1;
+                let start = self.pos;
+                if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                        self.pos += 1;
+                    }
+                } else {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                match name.as_str() {
+                    "{" | "lbrace" => Ok("{".to_string()),
+                    "}" | "rbrace" => Ok("}".to_string()),
+                    "|" | "Vert" => Ok("‖".to_string()),
+                    "vert" | "lvert" | "rvert" => Ok("|".to_string()),
+                    "langle" => Ok("⟨".to_string()),
+                    "rangle" => Ok("⟩".to_string()),
+                    "lfloor" => Ok("⌊".to_string()),
+                    "rfloor" => Ok("⌋".to_string()),
+                    "lceil" => Ok("⌈".to_string()),
+                    "rceil" => Ok("⌉".to_string()),
+                    _ => Err(format!("unknown delimiter '\\{}'", name)),
+                }
+            }
+            Some(c) if "()[]|/<>".contains(c) => {
+                self.pos += 1;
+                Ok(match c {
+                    '<' => "⟨".to_string(),
+                    '>' => "⟩".to_string(),
+                    c => c.to_string(),
+                })
+            }
+            _ => Err("missing delimiter".to_string()),
+        }
+    }
+
+    /// `\begin{name} ... \end{name}` for matrices, `cases` and `aligned`
+    fn environment(&mut self) -> Result<String, String> {
+        let name = self.text_argument()?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", "center"),
+            "pmatrix" => ("(", ")", "center"),
+            "bmatrix" => ("[", "]", "center"),
+            "Bmatrix" => ("{", "}", "center"),
+            "vmatrix" => ("|", "|", "center"),
+            "Vmatrix" => ("‖", "‖", "center"),
+            "cases" => ("{", "", "left left"),
+            "aligned" | "align" | "align*" | "split" => ("", "", "right left"),
+            "gathered" | "gather" | "gather*" => ("", "", "center"),
+            _ => return Err(format!("unknown environment '{}'", name)),
+        };
+
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            let cell = self.row()?;
+            cells.push(format!("<mtd>{}</mtd>", cell.concat()));
+            match self.peek() {
+                Some('&') => self.pos += 1,
+                Some('\\') if self.chars.get(self.pos + 1) == Some(&'\\') => {
+                    self.pos += 2;
+                    rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+                    cells.clear();
+                }
+                Some('\\') if self.at_command("end") => {
+                    self.pos += "\\end".len();
+                    let end = self.text_argument()?;
+                    if end != name {
+                        return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end));
+                    }
+                    break;
+                }
+                _ => return Err(format!("unclosed environment '{}'", name)),
+            }
+        }
+        // A trailing `\\` leaves an empty last row
+        if !(cells.len() == 1 && cells[0] == "<mtd></mtd>") {
+            rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+        }
+
+        let table = format!(
+            "<mtable columnalign=\"{}\">{}</mtable>",
+            html_attr(align),
+            rows.concat()
+        );
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+        Ok(format!(
+            "<mrow><mo fence=\"true\">{}</mo>{}<mo fence=\"true\">{}</mo></mrow>",
+            open, table, close
+        ))
+    }
+}
+
+/// Wraps several nodes in `<mrow>`; a single node stays as it is
+fn mrow(nodes: Vec<String>) -> String {
+    if nodes.len() == 1 {
+        nodes.into_iter().next().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", nodes.concat())
+    }
+}
+
+fn space(width: &str) -> String {
+    format!("<mspace width=\"{}\"></mspace>", width)
+}
+
+/// Letters and letter-like symbols
+fn identifier(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "infty" => "∞",
+        "emptyset" | "varnothing" => "∅",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        "wp" => "℘",
+        _ => return None,
+    })
+}
+
+/// Relations, binary operators, arrows and punctuation
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "cdot" | "cdotp" => "⋅",
+        "times" => "×",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "odot" => "⊙",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "mid" => "∣",
+        "parallel" => "∥",
+        "perp" => "⊥",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔",
+        "implies" | "Longrightarrow" => "⟹",
+        "impliedby" | "Longleftarrow" => "⟸",
+        "iff" | "Longleftrightarrow" => "⟺",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "lbrace" => "{",
+        "rbrace" => "}",
+        "vert" | "lvert" | "rvert" => "|",
+        "Vert" | "lVert" | "rVert" => "‖",
+        "angle" => "∠",
+        "top" => "⊤",
+        "bot" => "⊥",
+        "prime" => "′",
+        "colon" => ":",
+        "triangle" => "△",
+        "therefore" => "∴",
+        "because" => "∵",
+        _ => return None,
+    })
+}
+
+/// Big operators and whether their scripts become limits in display math
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "bigvee" => ("⋁", true),
+        "bigwedge" => ("⋀", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    })
+}
+
+/// Named functions set upright, and whether they take limits
+fn function(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "min" | "max" | "sup" | "inf" | "det" | "gcd" | "Pr"
+        | "argmin" | "argmax" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "ker" | "arg" | "hom" => {
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+/// Accent commands as (accent character, whether it stretches over the base)
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "hat" => ("^", false),
+        "widehat" => ("^", true),
+        "tilde" => ("~", false),
+        "widetilde" => ("~", true),
+        "bar" => ("¯", false),
+        "overline" => ("‾", true),
+        "vec" => ("→", false),
+        "overrightarrow" => ("→", true),
+        "dot" => ("˙", false),
+        "ddot" => ("¨", false),
+        "check" => ("ˇ", false),
+        "breve" => ("˘", false),
+        "acute" => ("´", false),
+        "grave" => ("`", false),
+        _ => return None,
+    })
+}
+
+/// `\mathbb{R}`, `\mathbf{x}` and similar, using the Unicode mathematical
+/// alphanumeric letters where they exist
+fn styled(command: &str, text: &str) -> String {
+    let letters: String = text
+        .chars()
+        .map(|c| styled_char(command, c).unwrap_or(c))
+        .collect();
+    let variant = match command {
+        "mathsf" => Some("sans-serif"),
+        "mathtt" => Some("monospace"),
+        _ => None,
+    };
+    match variant {
+        Some(variant) => format!(
+            "<mi mathvariant=\"{}\">{}</mi>",
+            variant,
+            html_text(&letters)
+        ),
+        None if letters.chars().count() > 1 => {
+            format!("<mi mathvariant=\"normal\">{}</mi>", html_text(&letters))
+        }
+        None => format!("<mi>{}</mi>", html_text(&letters)),
+    }
+}
+
+fn styled_char(command: &str, c: char) -> Option<char> {
+    let offset = |base: u32, start: char| char::from_u32(base + (c as u32 - start as u32));
+    match command {
+        "mathbb" => match c {
+            'C' => Some('ℂ'),
+            'H' => Some('ℍ'),
+            'N' => Some('ℕ'),
+            'P' => Some('ℙ'),
+            'Q' => Some('ℚ'),
+            'R' => Some('ℝ'),
+            'Z' => Some('ℤ'),
+            'A'..='Z' => offset(0x1D538, 'A'),
+            'a'..='z' => offset(0x1D552, 'a'),
+            '0'..='9' => offset(0x1D7D8, '0'),
+            _ => None,
+        },
+        "mathbf" => match c {
+            'A'..='Z' => offset(0x1D400, 'A'),
+            'a'..='z' => offset(0x1D41A, 'a'),
+            '0'..='9' => offset(0x1D7CE, '0'),
+            _ => None,
+        },
+        "boldsymbol" => match c {
+            'A'..='Z' => offset(0x1D468, 'A'),
+            'a'..='z' => offset(0x1D482, 'a'),
+            _ => None,
+        },
+        "mathit" => match c {
+            'h' => Some('ℎ'),
+            'A'..='Z' => offset(0x1D434, 'A'),
+            'a'..='z' => offset(0x1D44E, 'a'),
+            _ => None,
+        },
+        "mathcal" => match c {
+            'B' => Some('ℬ'),
+            'E' => Some('ℰ'),
+            'F' => Some('ℱ'),
+            'H' => Some('ℋ'),
+            'I' => Some('ℐ'),
+            'L' => Some('ℒ'),
+            'M' => Some('ℳ'),
+            'R' => Some('ℛ'),
+            'A'..='Z' => offset(0x1D49C, 'A'),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The MathML for `tex` without the `<math>` wrapper and annotation
+    fn body(tex: &str, display: bool) -> String {
+        let math = to_mathml(tex, display).unwrap();
+        let start = math.find("<semantics>").unwrap() + "<semantics>".len();
+        let end = math.find("<annotation").unwrap();
+        math[start..end].to_string()
+    }
+
+    fn inline(tex: &str) -> String {
+        body(tex, false)
+    }
+
+    #[test]
+    fn wraps_in_math_with_the_source() {
+        assert_eq!(
+            to_mathml("a < b", false).unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"inline\"><semantics><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow><annotation encoding=\"application/x-tex\">a &lt; b</annotation></semantics></math>"
+        );
+        assert!(to_mathml("x", true).unwrap().contains("display=\"block\""));
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(inline("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(inline("a_i"), "<msub><mi>a</mi><mi>i</mi></msub>");
+        assert_eq!(
+            inline("x_i^2"),
+            "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>"
+        );
+        assert_eq!(
+            inline("e^{i\\pi}"),
+            "<msup><mi>e</mi><mrow><mi>i</mi><mi>π</mi></mrow></msup>"
+        );
+        assert_eq!(inline("x''"), "<msup><mi>x</mi><mo>′′</mo></msup>");
+    }
+
+    #[test]
+    fn big_operators_take_limits_in_display_math() {
+        assert_eq!(
+            body("\\sum_{i=1}^n i", true),
+            "<mrow><munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mi>i</mi></mrow>"
+        );
+        assert_eq!(
+            inline("\\sum_{i=1}^n i"),
+            "<mrow><msubsup><mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></msubsup><mi>i</mi></mrow>"
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(
+            inline("\\frac{a}{b}"),
+            "<mfrac><mi>a</mi><mi>b</mi></mfrac>"
+        );
+        assert_eq!(inline("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+    }
+
+    #[test]
+    fn roots() {
+        assert_eq!(inline("\\sqrt{x}"), "<msqrt><mi>x</mi></msqrt>");
+        assert_eq!(
+            inline("\\sqrt[3]{x}"),
+            "<mroot><mi>x</mi><mn>3</mn></mroot>"
+        );
+    }
+
+    #[test]
+    fn environments() {
+        assert_eq!(
+            body("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}", true),
+            "<mrow><mo fence=\"true\">(</mo><mtable columnalign=\"center\"><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable><mo fence=\"true\">)</mo></mrow>"
+        );
+        assert_eq!(
+            body(
+                "\\begin{cases} 1 & x > 0 \\\\ 0 & \\text{otherwise} \\end{cases}",
+                true
+            ),
+            "<mrow><mo fence=\"true\">{</mo><mtable columnalign=\"left left\"><mtr><mtd><mn>1</mn></mtd><mtd><mi>x</mi><mo>&gt;</mo><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable><mo fence=\"true\"></mo></mrow>"
+        );
+        assert_eq!(
+            body("\\begin{aligned} a &= b \\\\ c &= d \\end{aligned}", true),
+            "<mtable columnalign=\"right left\"><mtr><mtd><mi>a</mi></mtd><mtd><mo>=</mo><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mo>=</mo><mi>d</mi></mtd></mtr></mtable>"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_environments() {
+        assert_eq!(
+            to_mathml("\\foo", false),
+            Err("unknown command '\\foo'".to_string())
+        );
+        assert_eq!(
+            to_mathml("\\begin{foo} a \\end{foo}", false),
+            Err("unknown environment 'foo'".to_string())
+        );
+        assert_eq!(
+            to_mathml("\\begin{matrix} a \\end{pmatrix}", false),
+            Err("\\begin{matrix} ended by \\end{pmatrix}".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        assert_eq!(to_mathml("{x", false), Err("expected '}'".to_string()));
+        assert_eq!(to_mathml("x}", false), Err("unexpected '}'".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_scripts() {
+        assert_eq!(to_mathml("x^", false), Err("missing argument".to_string()));
+        assert_eq!(
+            to_mathml("x^1^2", false),
+            Err("double superscript or subscript".to_string())
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Per-post Open Graph preview cards rendered at build time.
+//!
+//! Each card is a 1200×630 PNG with the site name, post title, author and
+//! date drawn over a solid colour or a background image.
+
+use ab_glyph::{point, Font, FontArc, PxScale, ScaleFont};
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+
+use crate::markdown::Post;
+use crate::BuildContext;
+
+/// Card dimensions recommended by Facebook, Twitter, LinkedIn and Discord.
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 630;
+/// Distance between the card edge and the text.
+const PADDING: f32 = 80.0;
+/// Longer titles are truncated with an ellipsis.
+const MAX_TITLE_LINES: usize = 4;
+
+/// Font used when `og_images.font` is not set. It is subset to Latin and
+/// common punctuation, so titles in other scripts need a configured font.
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+
+/// Renders the preview card for `post` and writes it to `output_path`.
+pub fn generate_card(
+    post: &Post,
+    output_path: &Path,
+    ctx: &BuildContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = &ctx.config;
+    let settings = &config.og_images;
+
+    let font = match &settings.font {
+        Some(path) => FontArc::try_from_vec(std::fs::read(ctx.root_dir.join(path))?)?,
+        None => FontArc::try_from_slice(DEFAULT_FONT)?,
+    };
+
+    let text_color = parse_hex_color(&settings.text_color)?;
+    let accent_color = parse_hex_color(&settings.accent_color)?;
+
+    // Background: an image (relative to `docs/`, like `profile_picture`) or a solid colour
+    let mut card = match &settings.background_image {
+        Some(path) => {
+            let background = image::open(ctx.output_dir.join(path.trim_start_matches('/')))?;
+            background
+                .resize_to_fill(WIDTH, HEIGHT, FilterType::Lanczos3)
+                .to_rgba8()
+        }
+        None => RgbaImage::from_pixel(WIDTH, HEIGHT, parse_hex_color(&settings.background)?),
+    };
+
+    // Accent bar down the left edge
+    for y in 0..HEIGHT {
+        for x in 0..16 {
+            card.put_pixel(x, y, accent_color);
+        }
+    }
+
+    // Site name at the top
+    draw_text(
+        &mut card,
+        &font,
+        &config.site.title,
+        36.0,
+        PADDING,
+        PADDING,
+        accent_color,
+    );
+
+    // Title, wrapped to fit and vertically centred
+    let title_size = 68.0;
+    let line_height = title_size * 1.2;
+    let lines = wrap_text(
+        &font,
+        &post.front_matter.title,
+        title_size,
+        WIDTH as f32 - PADDING * 2.0,
+    );
+    let block_height = line_height * lines.len() as f32;
+    let mut y = (HEIGHT as f32 - block_height) / 2.0;
+    for line in &lines {
+        draw_text(&mut card, &font, line, title_size, PADDING, y, text_color);
+        y += line_height;
+    }
+
+    // Author and date along the bottom
+    let byline = format!("{} · {}", post.front_matter.author, post.front_matter.date);
+    draw_text(
+        &mut card,
+        &font,
+        &byline,
+        32.0,
+        PADDING,
+        HEIGHT as f32 - PADDING - 32.0,
+        text_color,
+    );
+
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    card.save(output_path)?;
+    Ok(())
+}
+
+/// Parses `#rrggbb` (or `rrggbb`) into an opaque pixel.
+fn parse_hex_color(value: &str) -> Result<Rgba<u8>, Box<dyn std::error::Error>> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid colour '{}', expected #rrggbb", value).into());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+/// Width in pixels of `text` set at `size`.
+fn text_width(font: &FontArc, text: &str, size: f32) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = previous {
+            width += scaled.kern(prev, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Greedily wraps `text` on word boundaries so no line exceeds `max_width`.
+fn wrap_text(font: &FontArc, text: &str, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", current, word)
+        };
+        if text_width(font, &candidate, size) <= max_width || current.is_empty() {
+            current = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut current, word.to_string()));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    if lines.len() > MAX_TITLE_LINES {
+        lines.truncate(MAX_TITLE_LINES);
+        let last = lines.last_mut().unwrap();
+        while !last.is_empty() && text_width(font, &format!("{}…", last), size) > max_width {
+            last.pop();
+        }
+        last.push('…');
+    }
+    lines
+}
+
+/// Draws a single line of text with its top edge at `y`, alpha-blending onto the card.
+fn draw_text(
+    card: &mut RgbaImage,
+    font: &FontArc,
+    text: &str,
+    size: f32,
+    x: f32,
+    y: f32,
+    color: Rgba<u8>,
+) {
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+    let mut caret = point(x, y + scaled.ascent());
+    let mut previous = None;
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(prev) = previous {
+            caret.x += scaled.kern(prev, id);
+        }
+        let glyph = id.with_scale_and_position(scale, caret);
+        caret.x += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i32 + gx as i32;
+            let py = bounds.min.y as i32 + gy as i32;
+            if px < 0 || py < 0 || px >= WIDTH as i32 || py >= HEIGHT as i32 {
+                return;
+            }
+            let pixel = card.get_pixel_mut(px as u32, py as u32);
+            let alpha = coverage.clamp(0.0, 1.0);
+            for i in 0..3 {
+                pixel[i] =
+                    (pixel[i] as f32 * (1.0 - alpha) + color[i] as f32 * alpha).round() as u8;
+            }
+        });
+    }
+}
@@ -0,0 +1,223 @@
+//! `init` and `new`: bootstrap a site, and create posts and pages in it.
+//!
+//! `init` writes a commented `config.toml`, starter content and the default
+//! `docs/assets`, all embedded in the binary.
+//!
+//! `new` creates posts and pages with their front matter filled in. Both go
+//! in `content/<slug>.md`; pages carry `type: page`, which builds them to
+//! `docs/<slug>.html` instead of `docs/posts/`. The default templates match
+//! what `parse_post_markdown` and `parse_page_markdown` expect. A site can override them, or add its own, by
+//! placing Markdown files in `archetypes/` (e.g. `archetypes/post.md` or
+//! `archetypes/audit.md`). Templates may use these placeholders:
+//!
+//! - `{{ title }}`: the title, escaped for a double-quoted YAML string
+//! - `{{ date }}`: today's date as YYYY-MM-DD
+//! - `{{ author }}`: `site.author` from `config.toml`, escaped like the title
+//! - `{{ slug }}`: the file name without `.md`
+
+use slug::slugify;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::BuildContext;
+
+/// Files written by `init`, as (path relative to the new site, contents)
+const INIT_FILES: &[(&str, &[u8])] = &[
+    (
+        "config.toml",
+        include_bytes!("../templates/init/config.toml"),
+    ),
+    (
+        "content/about.md",
+        include_bytes!("../templates/init/content/about.md"),
+    ),
+    (
+        "content/license.md",
+        include_bytes!("../templates/init/content/license.md"),
+    ),
+    (
+        "content/404.md",
+        include_bytes!("../templates/init/content/404.md"),
+    ),
+    (
+        "content/hello-world.md",
+        include_bytes!("../templates/init/content/hello-world.md"),
+    ),
+    (
+        "docs/assets/css/pico.min.css",
+        include_bytes!("../docs/assets/css/pico.min.css"),
+    ),
+    (
+        "docs/assets/styles.css",
+        include_bytes!("../docs/assets/styles.css"),
+    ),
+    (
+        "docs/assets/prism-custom-theme.css",
+        include_bytes!("../docs/assets/prism-custom-theme.css"),
+    ),
+    (
+        "docs/assets/prism/prism.min.js",
+        include_bytes!("../docs/assets/prism/prism.min.js"),
+    ),
+    (
+        "docs/assets/prism/prism-solidity.min.js",
+        include_bytes!("../docs/assets/prism/prism-solidity.min.js"),
+    ),
+    (
+        "docs/assets/prism/prism-line-numbers.min.js",
+        include_bytes!("../docs/assets/prism/prism-line-numbers.min.js"),
+    ),
+    (
+        "docs/assets/prism/prism-line-numbers.min.css",
+        include_bytes!("../docs/assets/prism/prism-line-numbers.min.css"),
+    ),
+    (
+        "docs/assets/js/feather.min.js",
+        include_bytes!("../docs/assets/js/feather.min.js"),
+    ),
+    (
+        "docs/assets/js/code-blocks.js",
+        include_bytes!("../docs/assets/js/code-blocks.js"),
+    ),
+];
+
+/// Profile picture referenced by the starter `config.toml`
+const PLACEHOLDER_PROFILE_PICTURE: &str = "docs/assets/img/profile.png";
+
+/// Creates a new site in `dir`, which must be empty or not exist yet.
+pub fn init_site(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(format!(
+            "{} is not empty, refusing to initialise a site there",
+            dir.display()
+        )
+        .into());
+    }
+
+    for (relative, contents) in INIT_FILES {
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents)?;
+        println!("Created: {}", path.display());
+    }
+
+    // A plain square in the default accent colour until the owner adds their own picture
+    let profile_path = dir.join(PLACEHOLDER_PROFILE_PICTURE);
+    if let Some(parent) = profile_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    image::RgbaImage::from_pixel(400, 400, image::Rgba([16, 149, 193, 255])).save(&profile_path)?;
+    println!("Created: {}", profile_path.display());
+
+    Ok(())
+}
+
+/// What `new` is creating, which decides the default template.
+#[derive(Clone, Copy)]
+pub enum ContentKind {
+    Post,
+    Page,
+}
+
+impl ContentKind {
+    /// Archetype used when none is given on the command line.
+    fn default_archetype(self) -> &'static str {
+        match self {
+            ContentKind::Post => "post",
+            ContentKind::Page => "page",
+        }
+    }
+
+    /// Template used when `archetypes/` has no file for this kind.
+    fn default_template(self) -> &'static str {
+        match self {
+            ContentKind::Post => DEFAULT_POST_ARCHETYPE,
+            ContentKind::Page => DEFAULT_PAGE_ARCHETYPE,
+        }
+    }
+}
+
+const DEFAULT_POST_ARCHETYPE: &str = r#"---
+title: "{{ title }}"
+date: "{{ date }}"
+author: "{{ author }}"
+# description: "One-line summary used for search results and link previews"
+draft: true
+---
+
+"#;
+
+const DEFAULT_PAGE_ARCHETYPE: &str = r#"---
+type: page
+title: "{{ title }}"
+author: "{{ author }}"
+# description: "One-line summary used for search results and link previews"
+draft: true
+---
+
+"#;
+
+/// Creates the Markdown file for a new post or page and returns its path.
+///
+/// Fails if the file already exists, or if a named archetype isn't found in
+/// `archetypes/`.
+pub fn new_content(
+    kind: ContentKind,
+    title: &str,
+    archetype: Option<&str>,
+    ctx: &BuildContext,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err(format!("Cannot derive a file name from title '{}'", title).into());
+    }
+
+    let path = ctx.source_dir.join(format!("{}.md", slug));
+    if path.exists() {
+        return Err(format!("{} already exists, not overwriting it", path.display()).into());
+    }
+
+    let template = load_archetype(kind, archetype, &ctx.root_dir)?;
+    let contents = template
+        .replace("{{ title }}", &yaml_escape(title))
+        .replace(
+            "{{ date }}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        )
+        .replace("{{ author }}", &yaml_escape(&ctx.config.site.author))
+        .replace("{{ slug }}", &slug);
+
+    fs::create_dir_all(&ctx.source_dir)?;
+    // `create_new` guards against a file appearing between the check above and now
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+
+    Ok(path)
+}
+
+/// Reads `archetypes/<name>.md`, falling back to the built-in template when
+/// the default archetype for `kind` has no file.
+fn load_archetype(
+    kind: ContentKind,
+    archetype: Option<&str>,
+    root_dir: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let name = archetype.unwrap_or(kind.default_archetype());
+    let path = root_dir.join("archetypes").join(format!("{}.md", name));
+
+    match fs::read_to_string(&path) {
+        Ok(template) => Ok(template),
+        Err(_) if archetype.is_none() => Ok(kind.default_template().to_string()),
+        Err(e) => Err(format!("Could not read archetype {}: {}", path.display(), e).into()),
+    }
+}
+
+/// Escapes a value for a double-quoted YAML string.
+fn yaml_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,62 +1,703 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use percent_encoding::percent_decode_str;
+use std::collections::hash_map::DefaultHasher;
 use std::fs;
-use std::path::Path;
-use tiny_http::{Header, Response, Server};
-
-pub fn start_server(port: u16) -> std::io::Result<()> {
-    let address = format!("0.0.0.0:{}", port);
-    let server =
-        Server::http(&address).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-    println!("Serving at http://{}", address);
-
-    for request in server.incoming_requests() {
-        let url = request.url().trim_start_matches('/');
-        let path = if url.is_empty() {
-            // If no file is specified, serve index.html
-            "docs/index.html".to_string()
-        } else {
-            // Serve requested file
-            format!("docs/{}", url)
-        };
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How `serve` listens and what it serves
+pub struct ServeOptions {
+    /// Output directory holding the generated site
+    pub root: PathBuf,
+    /// Interface to listen on, e.g. "127.0.0.1" or "0.0.0.0"
+    pub bind: String,
+    pub port: u16,
+    /// Open the site in the default browser once listening
+    pub open: bool,
+    /// `base_url` from the config and the local URL that replaces it in
+    /// HTML/XML/text responses, so canonical and OG links stay on the preview
+    pub rewrite_base_url: Option<(String, String)>,
+    /// Worker threads, so a slow client doesn't hold up other requests
+    pub workers: usize,
+}
+
+/// URL a browser on this machine can use to reach `bind:port`
+pub fn local_url(bind: &str, port: u16) -> String {
+    let host = match bind {
+        "0.0.0.0" | "::" | "[::]" => "localhost",
+        host => host,
+    };
+    if host.contains(':') && !host.starts_with('[') {
+        format!("http://[{}]:{}", host, port)
+    } else {
+        format!("http://{}:{}", host, port)
+    }
+}
+
+/// Serves the generated site in `options.root` (the output directory).
+pub fn start_server(options: &ServeOptions) -> std::io::Result<()> {
+    // Canonical so resolved request paths can be compared against it
+    let root = options.root.canonicalize().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("cannot serve {}: {}", options.root.display(), e),
+        )
+    })?;
+    let address = if options.bind.contains(':') && !options.bind.starts_with('[') {
+        format!("[{}]:{}", options.bind, options.port)
+    } else {
+        format!("{}:{}", options.bind, options.port)
+    };
+    let server = Arc::new(Server::http(&address).map_err(std::io::Error::other)?);
+    let url = local_url(&options.bind, options.port);
+    println!("Serving {} at {}", options.root.display(), url);
+
+    if matches!(options.bind.as_str(), "0.0.0.0" | "::" | "[::]") {
+        println!("Warning: listening on all interfaces, the site is reachable from your network");
+    }
+
+    if options.open {
+        open_in_browser(&url);
+    }
+
+    let workers = options.workers.max(1);
+    let shutting_down = Arc::new(AtomicBool::new(false));
+
+    // Each unblock() wakes one worker, after any requests already queued
+    let result = ctrlc::set_handler({
+        let server = Arc::clone(&server);
+        let shutting_down = Arc::clone(&shutting_down);
+        move || {
+            if shutting_down.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            println!("Shutting down, finishing in-flight requests...");
+            for _ in 0..workers {
+                server.unblock();
+            }
+        }
+    });
+    if let Err(e) = result {
+        println!("Warning: could not install the Ctrl-C handler: {}", e);
+    }
 
-        let path_obj = Path::new(&path);
-        if path_obj.is_file() {
-            match fs::read(&path) {
-                Ok(contents) => {
-                    let mut response = Response::from_data(contents);
-
-                    // Basic MIME type detection
-                    if path.ends_with(".html") {
-                        response = response.with_header(
-                            "Content-Type: text/html; charset=utf-8"
-                                .parse::<Header>()
-                                .unwrap(),
-                        );
-                    } else if path.ends_with(".css") {
-                        response = response.with_header(
-                            "Content-Type: text/css; charset=utf-8"
-                                .parse::<Header>()
-                                .unwrap(),
-                        );
-                    } else if path.ends_with(".js") {
-                        response = response.with_header(
-                            "Content-Type: application/javascript; charset=utf-8"
-                                .parse::<Header>()
-                                .unwrap(),
-                        );
-                    }
-
-                    request.respond(response)?;
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                match server.recv() {
+                    Ok(request) => handle_request(request, &root, options),
+                    // recv() fails once unblocked by the Ctrl-C handler
+                    Err(_) if shutting_down.load(Ordering::SeqCst) => break,
+                    Err(e) => println!("Error accepting request: {}", e),
                 }
-                Err(_) => {
-                    let not_found = Response::from_string("404 Not Found").with_status_code(404);
-                    request.respond(not_found)?;
+            });
+        }
+    });
+
+    println!("Server stopped");
+    Ok(())
+}
+
+/// Responds to one request and prints its access log line.
+fn handle_request(request: Request, root: &Path, options: &ServeOptions) {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let url = request.url().to_string();
+
+    let response = if !matches!(method, Method::Get | Method::Head) {
+        Response::from_string("405 Method Not Allowed")
+            .with_status_code(405)
+            .with_header(header("Allow", "GET, HEAD"))
+    } else {
+        let response = match resolve_path(root, &url) {
+            Some(Target::File(path)) => file_response(&request, &path, options).ok(),
+            Some(Target::Redirect(location)) => Some(
+                Response::from_data(Vec::new())
+                    .with_status_code(301)
+                    .with_header(header("Location", &location)),
+            ),
+            None => None,
+        };
+        response.unwrap_or_else(|| not_found(root, options))
+    };
+
+    let status = response.status_code().0;
+    // HEAD and 304 responses carry headers only
+    let bytes = match (&method, status) {
+        (Method::Head, _) | (_, 304) => 0,
+        _ => response.data_length().unwrap_or(0),
+    };
+
+    let result = request.respond(response);
+    let elapsed = started.elapsed();
+    println!(
+        "{} {} {} {} {}B {:.1}ms{}",
+        chrono::Local::now().format("%H:%M:%S"),
+        method,
+        url,
+        status,
+        bytes,
+        elapsed.as_secs_f64() * 1000.0,
+        match result {
+            Ok(()) => String::new(),
+            // Usually the client going away mid-response
+            Err(e) => format!(" (send failed: {})", e),
+        }
+    );
+}
+
+/// What a request URL resolved to
+#[derive(Debug, PartialEq)]
+enum Target {
+    File(PathBuf),
+    /// A directory requested without its trailing slash; redirect so relative
+    /// links in its `index.html` resolve against the directory
+    Redirect(String),
+}
+
+/// Maps a request URL to a file under `root`, which must be canonical.
+///
+/// The query string and fragment are dropped and the path percent-decoded.
+/// Directories serve their `index.html`, and extensionless paths fall back to
+/// `<path>.html`, so `/posts/hello` serves `posts/hello.html`.
+///
+/// Returns `None` for anything that isn't an existing file inside `root`:
+/// `..` segments, drive or root prefixes, NUL bytes, and symlinks pointing
+/// elsewhere are all rejected rather than resolved.
+fn resolve_path(root: &Path, url: &str) -> Option<Target> {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let decoded = percent_decode_str(path).decode_utf8().ok()?;
+    if decoded.contains('\0') {
+        return None;
+    }
+
+    let mut resolved = root.to_path_buf();
+    // Backslashes count as separators so `..\` is caught on every platform
+    for segment in decoded.split(['/', '\\']) {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            segment => {
+                let only_normal = Path::new(segment)
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_)));
+                if !only_normal {
+                    return None;
                 }
+                resolved.push(segment);
+            }
+        }
+    }
+
+    if resolved.is_dir() {
+        if resolved != root && !path.ends_with('/') {
+            let query = url.split_once('?').map_or("", |(_, query)| query);
+            let query = query.split('#').next().unwrap_or("");
+            return Some(Target::Redirect(if query.is_empty() {
+                format!("{}/", path)
+            } else {
+                format!("{}/?{}", path, query)
+            }));
+        }
+        resolved.push("index.html");
+    } else if !resolved.exists() && resolved.extension().is_none() {
+        resolved.set_extension("html");
+    }
+
+    // Catches symlinks that lead out of the output directory
+    let canonical = resolved.canonicalize().ok()?;
+    (canonical.starts_with(root) && canonical.is_file()).then_some(Target::File(canonical))
+}
+
+/// The site's `404.html` (generated from `content/404.md`) with a 404
+/// status, or a plain-text message if the site has none.
+fn not_found(root: &Path, options: &ServeOptions) -> Response<Cursor<Vec<u8>>> {
+    match read_file(&root.join("404.html"), options) {
+        Ok(contents) => Response::from_data(contents)
+            .with_status_code(404)
+            .with_header(header("Content-Type", content_type("html"))),
+        Err(_) => Response::from_string("404 Not Found").with_status_code(404),
+    }
+}
+
+/// Reads a file to serve, pointing production URLs at the preview in text
+/// files.
+fn read_file(path: &Path, options: &ServeOptions) -> io::Result<Vec<u8>> {
+    let contents = fs::read(path)?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    Ok(match &options.rewrite_base_url {
+        Some((from, to)) if matches!(extension, "html" | "xml" | "txt") => {
+            String::from_utf8_lossy(&contents)
+                .replace(from.as_str(), to)
+                .into_bytes()
+        }
+        _ => contents,
+    })
+}
+
+/// Responses smaller than this aren't worth compressing
+const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// Content-Encoding chosen from the request's Accept-Encoding
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// Builds the response for an existing file, honouring conditional, range
+/// and compression headers.
+fn file_response(
+    request: &Request,
+    path: &Path,
+    options: &ServeOptions,
+) -> io::Result<Response<Cursor<Vec<u8>>>> {
+    let contents = read_file(path, options)?;
+    let modified = fs::metadata(path)?.modified()?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let content_type = content_type(extension);
+    let range = request_header(request, "Range");
+
+    // Ranges are byte offsets into the uncompressed file, so don't compress when one is asked for
+    let encoding = match request_header(request, "Accept-Encoding") {
+        Some(accepted)
+            if range.is_none()
+                && is_compressible(content_type)
+                && contents.len() >= MIN_COMPRESS_SIZE =>
+        {
+            negotiate_encoding(accepted)
+        }
+        _ => Encoding::Identity,
+    };
+
+    // Each encoding is a different representation, so it gets its own tag
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    let etag = match encoding {
+        Encoding::Identity => format!("\"{:016x}\"", hasher.finish()),
+        encoding => format!("\"{:016x}-{}\"", hasher.finish(), encoding.name()),
+    };
+    let last_modified = httpdate::fmt_http_date(modified);
+
+    let headers = [
+        header("Content-Type", content_type),
+        header("ETag", &etag),
+        header("Last-Modified", &last_modified),
+        header("Accept-Ranges", "bytes"),
+        header("Vary", "Accept-Encoding"),
+    ];
+    let respond = |status: u16, body: Vec<u8>| {
+        let mut response = Response::from_data(body)
+            .with_status_code(status)
+            // Always send Content-Length, which HEAD and range clients rely on
+            .with_chunked_threshold(usize::MAX);
+        for header in headers.iter().cloned() {
+            response.add_header(header);
+        }
+        response
+    };
+
+    if is_not_modified(request, &etag, modified) {
+        return Ok(respond(304, Vec::new()));
+    }
+
+    // If-Range: only honour the range if the client's copy is still current
+    let range = range.filter(|_| match request_header(request, "If-Range") {
+        Some(validator) => validator == etag || validator == last_modified,
+        None => true,
+    });
+    if let Some(range) = range {
+        match parse_range(range, contents.len()) {
+            Some(Ok((start, end))) => {
+                let content_range = format!("bytes {}-{}/{}", start, end, contents.len());
+                return Ok(respond(206, contents[start..=end].to_vec())
+                    .with_header(header("Content-Range", &content_range)));
+            }
+            Some(Err(())) => {
+                let content_range = format!("bytes */{}", contents.len());
+                return Ok(
+                    respond(416, Vec::new()).with_header(header("Content-Range", &content_range))
+                );
             }
+            // Multiple or malformed ranges: send the whole file
+            None => {}
+        }
+    }
+
+    let response = match encoding {
+        Encoding::Identity => respond(200, contents),
+        encoding => respond(200, compress(&contents, encoding)?)
+            .with_header(header("Content-Encoding", encoding.name())),
+    };
+    Ok(response)
+}
+
+/// Whether the client's cached copy (per If-None-Match, or failing that
+/// If-Modified-Since) is still current.
+fn is_not_modified(request: &Request, etag: &str, modified: SystemTime) -> bool {
+    if let Some(tags) = request_header(request, "If-None-Match") {
+        return tags
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+    let Some(since) = request_header(request, "If-Modified-Since")
+        .and_then(|since| httpdate::parse_http_date(since).ok())
+    else {
+        return false;
+    };
+    // HTTP dates have whole-second precision
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let since = since
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    modified <= since
+}
+
+/// Parses a single `bytes=` range into inclusive offsets.
+///
+/// Returns `None` for anything this server doesn't handle (other units,
+/// multiple ranges, malformed values), and `Some(Err)` when the range lies
+/// outside the file.
+fn parse_range(value: &str, length: usize) -> Option<Result<(usize, usize), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = if start.is_empty() {
+        // Suffix range: the last `end` bytes
+        let suffix: usize = end.parse().ok()?;
+        if suffix == 0 {
+            return Some(Err(()));
+        }
+        (length.saturating_sub(suffix), length.saturating_sub(1))
+    } else {
+        let start: usize = start.parse().ok()?;
+        let end = if end.is_empty() {
+            length.saturating_sub(1)
         } else {
-            let not_found = Response::from_string("404 Not Found").with_status_code(404);
-            request.respond(not_found)?;
+            let end: usize = end.parse().ok()?;
+            end.min(length.saturating_sub(1))
+        };
+        (start, end)
+    };
+
+    if length == 0 || start >= length || start > end {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+/// Picks the preferred encoding the client accepts, favouring brotli on ties.
+fn negotiate_encoding(accept_encoding: &str) -> Encoding {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut wildcard = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "*" => wildcard = Some(quality),
+            _ => {}
         }
     }
 
-    Ok(())
+    let brotli = brotli.or(wildcard).unwrap_or(0.0);
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    if brotli > 0.0 && brotli >= gzip {
+        Encoding::Brotli
+    } else if gzip > 0.0 {
+        Encoding::Gzip
+    } else {
+        Encoding::Identity
+    }
+}
+
+fn compress(contents: &[u8], encoding: Encoding) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(contents.to_vec()),
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(contents)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            // Quality 5 keeps compression fast enough to do on every request
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            encoder.write_all(contents)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/// Content-Type for a file extension, defaulting to a generic binary type
+fn content_type(extension: &str) -> &'static str {
+    match extension.to_ascii_lowercase().as_str() {
+        // Documents
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "xml" | "xsl" => "application/xml; charset=utf-8",
+        "rss" => "application/rss+xml; charset=utf-8",
+        "atom" => "application/atom+xml; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "sol" => "text/plain; charset=utf-8",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        // Images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+        // Fonts
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        // Audio and video
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "mov" => "video/quicktime",
+        "vtt" => "text/vtt; charset=utf-8",
+        // Archives
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Text-like types that shrink under compression; images, media and woff
+/// fonts are already compressed.
+fn is_compressible(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type.starts_with("application/json")
+        || content_type.starts_with("application/manifest+json")
+        || content_type.starts_with("application/xml")
+        || content_type.starts_with("application/rss+xml")
+        || content_type.starts_with("application/atom+xml")
+        || content_type.starts_with("application/wasm")
+        || content_type.starts_with("image/svg+xml")
+        || content_type.starts_with("font/ttf")
+        || content_type.starts_with("font/otf")
+}
+
+/// Value of a request header, if present
+fn request_header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+/// Opens `url` with the platform's default handler, reporting (not failing) on error
+fn open_in_browser(url: &str) {
+    #[cfg(target_os = "macos")]
+    let result = std::process::Command::new("open").arg(url).spawn();
+    #[cfg(target_os = "windows")]
+    let result = std::process::Command::new("cmd")
+        .args(["/C", "start", "", url])
+        .spawn();
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let result = std::process::Command::new("xdg-open").arg(url).spawn();
+
+    if let Err(e) = result {
+        println!("Could not open a browser ({}), visit {} instead", e, url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds `<tmp>/<name>/site` containing a couple of pages, with a
+    /// `secret.txt` next to it that must never be served.
+    fn site(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("xeniria-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("site");
+        fs::create_dir_all(root.join("posts")).unwrap();
+        fs::write(root.join("index.html"), "home").unwrap();
+        fs::write(root.join("posts/hello.html"), "post").unwrap();
+        fs::create_dir_all(root.join("archive")).unwrap();
+        fs::write(root.join("archive/index.html"), "archive").unwrap();
+        fs::write(base.join("secret.txt"), "secret").unwrap();
+        root.canonicalize().unwrap()
+    }
+
+    fn file(root: &Path, relative: &str) -> Option<Target> {
+        Some(Target::File(root.join(relative)))
+    }
+
+    #[test]
+    fn serves_files_inside_root() {
+        let root = site("inside");
+        assert_eq!(resolve_path(&root, "/"), file(&root, "index.html"));
+        assert_eq!(
+            resolve_path(&root, "/posts/hello.html"),
+            file(&root, "posts/hello.html")
+        );
+        assert_eq!(
+            resolve_path(&root, "/posts/./hello.html"),
+            file(&root, "posts/hello.html")
+        );
+        assert_eq!(
+            resolve_path(&root, "/posts%2Fhello.html"),
+            file(&root, "posts/hello.html")
+        );
+    }
+
+    #[test]
+    fn strips_query_and_fragment() {
+        let root = site("query");
+        let expected = file(&root, "posts/hello.html");
+        assert_eq!(resolve_path(&root, "/posts/hello.html?v=1"), expected);
+        assert_eq!(resolve_path(&root, "/posts/hello.html#intro"), expected);
+        assert_eq!(
+            resolve_path(&root, "/posts/hello.html?next=../../secret.txt"),
+            expected
+        );
+        assert_eq!(resolve_path(&root, "/?x=1"), file(&root, "index.html"));
+    }
+
+    #[test]
+    fn rejects_plain_traversal() {
+        let root = site("plain");
+        assert_eq!(resolve_path(&root, "/../secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/posts/../../secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/posts/../index.html"), None);
+        assert_eq!(resolve_path(&root, "/..\\secret.txt"), None);
+    }
+
+    #[test]
+    fn rejects_encoded_traversal() {
+        let root = site("encoded");
+        for url in [
+            "/%2e%2e/secret.txt",
+            "/%2E%2E/secret.txt",
+            "/..%2fsecret.txt",
+            "/..%2Fsecret.txt",
+            "/%2e%2e%2fsecret.txt",
+            "/posts/%2e%2e%2f%2e%2e%2fsecret.txt",
+            "/..%5csecret.txt",
+            "/%2e%2e%5csecret.txt",
+            "/.%2e/secret.txt",
+        ] {
+            assert_eq!(resolve_path(&root, url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn double_encoding_is_decoded_once() {
+        let root = site("double");
+        // Decodes to a literal `%2e%2e` directory name, which doesn't exist
+        assert_eq!(resolve_path(&root, "/%252e%252e/secret.txt"), None);
+        assert_eq!(resolve_path(&root, "/%252e%252e%252fsecret.txt"), None);
+    }
+
+    #[test]
+    fn rejects_absolute_and_malformed_paths() {
+        let root = site("absolute");
+        let secret = root.parent().unwrap().join("secret.txt");
+        let absolute = format!("/{}", secret.display());
+        assert_eq!(resolve_path(&root, &absolute), None);
+        assert_eq!(resolve_path(&root, "/index.html%00.png"), None);
+        assert_eq!(resolve_path(&root, "/%ff%fe"), None);
+        assert_eq!(resolve_path(&root, "/missing.html"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_leaving_root() {
+        let root = site("symlink");
+        let secret = root.parent().unwrap().join("secret.txt");
+        std::os::unix::fs::symlink(&secret, root.join("leak.txt")).unwrap();
+        assert_eq!(resolve_path(&root, "/leak.txt"), None);
+    }
+
+    #[test]
+    fn directories_serve_their_index() {
+        let root = site("directory");
+        assert_eq!(
+            resolve_path(&root, "/archive/"),
+            file(&root, "archive/index.html")
+        );
+        assert_eq!(
+            resolve_path(&root, "/archive"),
+            Some(Target::Redirect("/archive/".to_string()))
+        );
+        assert_eq!(
+            resolve_path(&root, "/archive?page=2#top"),
+            Some(Target::Redirect("/archive/?page=2".to_string()))
+        );
+        // No index.html to serve
+        assert_eq!(resolve_path(&root, "/posts/"), None);
+    }
+
+    #[test]
+    fn extensionless_paths_fall_back_to_html() {
+        let root = site("extensionless");
+        assert_eq!(
+            resolve_path(&root, "/posts/hello"),
+            file(&root, "posts/hello.html")
+        );
+        assert_eq!(
+            resolve_path(&root, "/posts/hello?ref=feed"),
+            file(&root, "posts/hello.html")
+        );
+        assert_eq!(resolve_path(&root, "/posts/missing"), None);
+        assert_eq!(resolve_path(&root, "/%2e%2e/secret"), None);
+    }
 }
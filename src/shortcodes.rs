@@ -0,0 +1,565 @@
+//! Shortcodes: reusable components written inside Markdown.
+//!
+//! ```md
+//! {{< youtube id="dQw4w9WgXcQ" title="Demo" >}}
+//!
+//! {{< callout type="warning" title="Reentrancy" >}}
+//! Update balances **before** making the external call.
+//! {{< /callout >}}
+//! ```
+//!
+//! Shortcodes are expanded before the Markdown is parsed. A shortcode with a
+//! matching `{{< /name >}}` wraps the content in between, which is still
+//! rendered as Markdown; any other shortcode stands alone. Arguments are
+//! `key="value"` pairs (single quotes or bare words work too), and unnamed
+//! arguments are available by position as `0`, `1`, ...
+//!
+//! Built in: `callout`, `figure`, `youtube` (embedded from
+//! youtube-nocookie.com), `gist` (the code is fetched at build time and
+//! rendered as a code block) and `tweet` (a static quote, no X/Twitter
+//! script).
+//!
+//! A site adds its own, or replaces a built-in, with an HTML template in
+//! `shortcodes/<name>.html`. `{{ key }}` in the template is replaced by that
+//! argument, HTML-escaped (missing arguments are empty), and `{{ inner }}` by
+//! the wrapped content, rendered as Markdown; keep `{{ inner }}` on a line of
+//! its own.
+//!
+//! Shortcodes inside code blocks and inline code are left alone, and
+//! `{{</* name */>}}` writes a literal `{{< name >}}`.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::escape::{html_attr, html_text, url_attr};
+
+type Error = Box<dyn std::error::Error>;
+
+/// Stands in for code while shortcodes are expanded; never appears in Markdown
+const CODE_MARKER: char = '\u{FDD0}';
+
+/// Arguments of one shortcode, by name or position
+type Args = BTreeMap<String, String>;
+
+/// Expands every shortcode in `markdown`, looking up site templates in
+/// `<root_dir>/shortcodes/`.
+pub fn expand(markdown: &str, root_dir: &Path) -> Result<String, Error> {
+    if !markdown.contains("{{<") {
+        return Ok(markdown.to_string());
+    }
+
+    let (masked, code) = mask_code(markdown);
+    let expander = Expander {
+        templates: root_dir.join("shortcodes"),
+    };
+    let expanded = expander.expand(&masked)?;
+    Ok(unmask_code(&expanded, &code))
+}
+
+struct Expander {
+    templates: PathBuf,
+}
+
+/// A parsed `{{< ... >}}` tag
+struct Tag {
+    /// Byte range of the whole tag in the text being expanded
+    start: usize,
+    end: usize,
+    name: String,
+    args: Args,
+    closing: bool,
+    /// `{{</* ... */>}}`, written out literally
+    escaped: bool,
+}
+
+impl Expander {
+    fn expand(&self, text: &str) -> Result<String, Error> {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(tag) = next_tag(rest)? {
+            output.push_str(&rest[..tag.start]);
+
+            if tag.escaped {
+                output.push_str(
+                    &rest[tag.start..tag.end]
+                        .replace("{{</*", "{{<")
+                        .replace("*/>}}", ">}}"),
+                );
+                rest = &rest[tag.end..];
+                continue;
+            }
+            if tag.closing {
+                return Err(format!(
+                    "Unexpected {{{{< /{} >}}}} without an opening tag",
+                    tag.name
+                )
+                .into());
+            }
+
+            let after = &rest[tag.end..];
+            match find_closing(after, &tag.name)? {
+                Some((inner_end, close_end)) => {
+                    let inner = self.expand(&after[..inner_end])?;
+                    output.push_str(&self.render(&tag.name, &tag.args, Some(&inner))?);
+                    rest = &after[close_end..];
+                }
+                None => {
+                    output.push_str(&self.render(&tag.name, &tag.args, None)?);
+                    rest = after;
+                }
+            }
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Output for one shortcode; `inner` is the (expanded) wrapped Markdown
+    fn render(&self, name: &str, args: &Args, inner: Option<&str>) -> Result<String, Error> {
+        let template = self.templates.join(format!("{}.html", name));
+        if template.is_file() {
+            let template = fs::read_to_string(&template)
+                .map_err(|e| format!("Could not read shortcode {}: {}", template.display(), e))?;
+            return Ok(render_template(&template, args, inner));
+        }
+
+        match name {
+            "callout" => callout(args, inner),
+            "figure" => figure(args, inner),
+            "youtube" => youtube(args),
+            "gist" => gist(args),
+            "tweet" => tweet(args, inner),
+            _ => Err(format!(
+                "Unknown shortcode '{}' (add it as {})",
+                name,
+                template.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Finds the next shortcode tag in `text`.
+fn next_tag(text: &str) -> Result<Option<Tag>, Error> {
+    let Some(start) = text.find("{{<") else {
+        return Ok(None);
+    };
+    let Some(length) = text[start..].find(">}}") else {
+        return Err("Unterminated shortcode, expected '>}}'".into());
+    };
+    let end = start + length + 3;
+    let mut body = text[start + 3..end - 3].trim();
+
+    let escaped = body.starts_with("/*") && body.ends_with("*/");
+    if escaped {
+        body = body[2..body.len() - 2].trim();
+    }
+    let closing = body.starts_with('/');
+    let body = body.trim_start_matches('/').trim();
+
+    let (name, args) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+    if name.is_empty() {
+        return Err(format!("Shortcode without a name: {}", &text[start..end]).into());
+    }
+
+    Ok(Some(Tag {
+        start,
+        end,
+        name: name.to_string(),
+        args: parse_args(args)?,
+        closing,
+        escaped,
+    }))
+}
+
+/// Finds `{{< /name >}}` matching an opening tag, allowing nested tags with the
+/// same name. Returns where the wrapped content ends and the closing tag ends.
+fn find_closing(text: &str, name: &str) -> Result<Option<(usize, usize)>, Error> {
+    let mut depth = 0;
+    let mut offset = 0;
+
+    while let Some(tag) = next_tag(&text[offset..])? {
+        let (start, end) = (offset + tag.start, offset + tag.end);
+        offset = end;
+        if tag.escaped || tag.name != name {
+            continue;
+        }
+        if !tag.closing {
+            // Only a paired tag of the same name nests; a standalone one closes nothing
+            if has_closing(&text[end..], name)? {
+                depth += 1;
+            }
+        } else if depth == 0 {
+            return Ok(Some((start, end)));
+        } else {
+            depth -= 1;
+        }
+    }
+    Ok(None)
+}
+
+/// Whether a closing tag for `name` appears anywhere in `text`
+fn has_closing(text: &str, name: &str) -> Result<bool, Error> {
+    let mut offset = 0;
+    while let Some(tag) = next_tag(&text[offset..])? {
+        if tag.closing && !tag.escaped && tag.name == name {
+            return Ok(true);
+        }
+        offset += tag.end;
+    }
+    Ok(false)
+}
+
+/// Parses `key="value" key='value' key=value "positional"` arguments.
+fn parse_args(text: &str) -> Result<Args, Error> {
+    static ARG: OnceLock<Regex> = OnceLock::new();
+    let arg = ARG.get_or_init(|| {
+        Regex::new(
+            r#"^\s*(?:([A-Za-z_][\w-]*)\s*=\s*)?(?:"((?:[^"\\]|\\.)*)"|'([^']*)'|([^\s"']+))"#,
+        )
+        .unwrap()
+    });
+
+    let mut args = Args::new();
+    let mut rest = text;
+    let mut position = 0;
+    while !rest.trim().is_empty() {
+        let caps = arg
+            .captures(rest)
+            .ok_or_else(|| format!("Could not parse shortcode arguments: {}", rest.trim()))?;
+        let value = match (caps.get(2), caps.get(3), caps.get(4)) {
+            (Some(quoted), _, _) => quoted.as_str().replace("\\\"", "\"").replace("\\\\", "\\"),
+            (_, Some(single), _) => single.as_str().to_string(),
+            (_, _, Some(bare)) => bare.as_str().to_string(),
+            _ => unreachable!("the pattern requires a value"),
+        };
+        let key = match caps.get(1) {
+            Some(key) => key.as_str().to_string(),
+            None => {
+                position += 1;
+                (position - 1).to_string()
+            }
+        };
+        args.insert(key, value);
+        rest = &rest[caps.get(0).unwrap().end()..];
+    }
+    Ok(args)
+}
+
+/// Replaces fenced code blocks and inline code spans with markers so
+/// shortcode-like text in code is left alone. Returns the masked text and the
+/// code it stands for, in order.
+fn mask_code(markdown: &str) -> (String, Vec<String>) {
+    let mut masked = String::with_capacity(markdown.len());
+    let mut code = Vec::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut block = String::new();
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_len = fence_char.map_or(0, |c| trimmed.chars().take_while(|x| *x == c).count());
+
+        match fence {
+            Some((c, len)) => {
+                block.push_str(line);
+                let closes = fence_char == Some(c)
+                    && fence_len >= len
+                    && trimmed[fence_len..].trim().is_empty();
+                if closes {
+                    push_marker(&mut masked, &mut code, std::mem::take(&mut block));
+                    fence = None;
+                }
+            }
+            None if fence_len >= 3 && line.len() - trimmed.len() < 4 => {
+                fence = Some((fence_char.unwrap(), fence_len));
+                block.push_str(line);
+            }
+            None => mask_inline_code(line, &mut masked, &mut code),
+        }
+    }
+    // An unclosed fence runs to the end of the document
+    if !block.is_empty() {
+        push_marker(&mut masked, &mut code, block);
+    }
+    (masked, code)
+}
+
+/// Masks backtick code spans within one line
+fn mask_inline_code(line: &str, masked: &mut String, code: &mut Vec<String>) {
+    let mut rest = line;
+    while let Some(start) = rest.find('`') {
+        let ticks = rest[start..].chars().take_while(|c| *c == '`').count();
+        let delimiter = "`".repeat(ticks);
+        let content_start = start + ticks;
+        // The span ends at the next run of exactly the same length
+        let mut search = content_start;
+        let mut end = None;
+        while let Some(found) = rest[search..].find(&delimiter) {
+            let at = search + found;
+            let run = rest[at..].chars().take_while(|c| *c == '`').count();
+            if run == ticks {
+                end = Some(at + ticks);
+                break;
+            }
+            search = at + run;
+        }
+
+        match end {
+            Some(end) => {
+                masked.push_str(&rest[..start]);
+                push_marker(masked, code, rest[start..end].to_string());
+                rest = &rest[end..];
+            }
+            None => {
+                masked.push_str(&rest[..content_start]);
+                rest = &rest[content_start..];
+            }
+        }
+    }
+    masked.push_str(rest);
+}
+
+fn push_marker(masked: &mut String, code: &mut Vec<String>, text: String) {
+    masked.push(CODE_MARKER);
+    masked.push_str(&code.len().to_string());
+    masked.push(CODE_MARKER);
+    code.push(text);
+}
+
+fn unmask_code(text: &str, code: &[String]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut parts = text.split(CODE_MARKER);
+    output.push_str(parts.next().unwrap_or(""));
+    while let (Some(index), Some(after)) = (parts.next(), parts.next()) {
+        match index.parse::<usize>().ok().and_then(|i| code.get(i)) {
+            Some(original) => output.push_str(original),
+            None => output.push_str(index),
+        }
+        output.push_str(after);
+    }
+    output
+}
+
+/// Wraps Markdown so it is parsed as Markdown between two lines of HTML
+fn markdown_block(inner: &str) -> String {
+    format!("\n\n{}\n\n", inner.trim_matches('\n'))
+}
+
+/// Fills a `shortcodes/<name>.html` template.
+fn render_template(template: &str, args: &Args, inner: Option<&str>) -> String {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let placeholder = PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([\w-]+)\s*\}\}").unwrap());
+
+    let rendered = placeholder.replace_all(template, |caps: &regex::Captures| match &caps[1] {
+        "inner" => markdown_block(inner.unwrap_or("")),
+        key => html_attr(args.get(key).map(String::as_str).unwrap_or("")),
+    });
+    // Surrounding blank lines keep the HTML a block of its own
+    format!("\n\n{}\n\n", rendered.trim())
+}
+
+fn required<'a>(args: &'a Args, shortcode: &str, key: &str) -> Result<&'a str, Error> {
+    args.get(key)
+        .map(String::as_str)
+        .ok_or_else(|| format!("Shortcode '{}' needs a {} argument", shortcode, key).into())
+}
+
+/// `{{< callout type="note|tip|info|warning|danger" title="..." >}}...{{< /callout >}}`
+fn callout(args: &Args, inner: Option<&str>) -> Result<String, Error> {
+    let kind = args.get("type").map(String::as_str).unwrap_or("note");
+    if !matches!(kind, "note" | "tip" | "info" | "warning" | "danger") {
+        return Err(format!(
+            "Unknown callout type '{}', expected note, tip, info, warning or danger",
+            kind
+        )
+        .into());
+    }
+
+    let title = args
+        .get("title")
+        .map(|title| format!("\n<p class=\"callout-title\">{}</p>", html_text(title)))
+        .unwrap_or_default();
+    Ok(format!(
+        "\n\n<aside class=\"callout callout-{kind}\" role=\"note\">{title}{inner}</aside>\n\n",
+        kind = kind,
+        title = title,
+        inner = markdown_block(inner.unwrap_or("")),
+    ))
+}
+
+/// `{{< figure src="..." alt="..." caption="..." >}}`, or with the caption
+/// (as Markdown) between the tags
+fn figure(args: &Args, inner: Option<&str>) -> Result<String, Error> {
+    let src = required(args, "figure", "src")?;
+    let alt = args.get("alt").map(String::as_str).unwrap_or("");
+
+    let caption = match (inner, args.get("caption")) {
+        (Some(inner), _) if !inner.trim().is_empty() => inner.trim().to_string(),
+        (_, Some(caption)) => html_text(caption),
+        _ => String::new(),
+    };
+    let caption = if caption.is_empty() {
+        String::new()
+    } else {
+        format!("<figcaption>\n\n{}\n\n</figcaption>\n", caption)
+    };
+
+    // The image stays Markdown so it goes through the usual image handling
+    let alt = alt
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]");
+    Ok(format!(
+        "\n\n<figure class=\"figure\">\n\n![{alt}](<{src}>)\n\n{caption}</figure>\n\n",
+        alt = alt,
+        src = src.replace(['<', '>', '\n'], ""),
+        caption = caption,
+    ))
+}
+
+/// `{{< youtube id="..." title="..." start="90" >}}`, embedded without
+/// tracking cookies
+fn youtube(args: &Args) -> Result<String, Error> {
+    let id = args
+        .get("id")
+        .or_else(|| args.get("0"))
+        .ok_or("Shortcode 'youtube' needs an id argument")?;
+    if id.is_empty()
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("Invalid YouTube video id '{}'", id).into());
+    }
+    let start = match args.get("start") {
+        Some(start) => {
+            let seconds: u32 = start
+                .parse()
+                .map_err(|_| format!("Invalid YouTube start time '{}', expected seconds", start))?;
+            format!("?start={}", seconds)
+        }
+        None => String::new(),
+    };
+    let title = args
+        .get("title")
+        .map(String::as_str)
+        .unwrap_or("YouTube video");
+
+    Ok(format!(
+        "\n\n<div class=\"video-embed\"><iframe src=\"https://www.youtube-nocookie.com/embed/{id}{start}\" title=\"{title}\" loading=\"lazy\" allow=\"accelerometer; encrypted-media; gyroscope; picture-in-picture\" referrerpolicy=\"strict-origin-when-cross-origin\" allowfullscreen></iframe></div>\n\n",
+        id = id,
+        start = start,
+        title = html_attr(title),
+    ))
+}
+
+/// `{{< gist user="..." id="..." file="Token.sol" >}}`: the gist's code as a
+/// regular code block, so pages don't load GitHub's embed script
+fn gist(args: &Args) -> Result<String, Error> {
+    let user = required(args, "gist", "user")?;
+    let id = required(args, "gist", "id")?;
+    let valid = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    if !valid(user) || !valid(id) {
+        return Err(format!("Invalid gist '{}/{}'", user, id).into());
+    }
+
+    let page_url = format!("https://gist.github.com/{}/{}", user, id);
+    let file = args.get("file");
+    let raw_url = match file {
+        Some(file) => format!(
+            "https://gist.githubusercontent.com/{}/{}/raw/{}",
+            user,
+            id,
+            percent_encoding::utf8_percent_encode(file, percent_encoding::NON_ALPHANUMERIC)
+        ),
+        None => format!("https://gist.githubusercontent.com/{}/{}/raw", user, id),
+    };
+    let label = file.map(String::as_str).unwrap_or(id);
+    let link = format!(
+        "<p class=\"gist-source\"><a href=\"{}\">{}</a> on GitHub Gist</p>",
+        url_attr(&page_url),
+        html_text(label)
+    );
+
+    let code = match fetch_text(&raw_url) {
+        Some(code) => code,
+        None => {
+            println!("Could not fetch gist '{}', linking to it instead", page_url);
+            return Ok(format!("\n\n{}\n\n", link));
+        }
+    };
+
+    // Longer than any backtick run in the code, so the fence can't be closed early
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let language = args
+        .get("lang")
+        .map(String::as_str)
+        .or_else(|| {
+            file.and_then(|f| Path::new(f).extension())
+                .and_then(|e| e.to_str())
+        })
+        .unwrap_or("");
+
+    Ok(format!(
+        "\n\n{fence}{language}\n{code}\n{fence}\n\n{link}\n\n",
+        fence = fence,
+        language = language,
+        code = code.trim_end_matches('\n'),
+        link = link,
+    ))
+}
+
+/// `{{< tweet user="..." id="..." date="..." >}}text{{< /tweet >}}`: a quote
+/// linking to the post, with no X/Twitter script or tracking
+fn tweet(args: &Args, inner: Option<&str>) -> Result<String, Error> {
+    let user = required(args, "tweet", "user")?.trim_start_matches('@');
+    let id = required(args, "tweet", "id")?;
+    let valid_user =
+        !user.is_empty() && user.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_user || id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid tweet '{}/{}'", user, id).into());
+    }
+
+    let url = format!("https://x.com/{}/status/{}", user, id);
+    let link_text = args
+        .get("date")
+        .map(|date| html_text(date))
+        .unwrap_or_else(|| "View on X".to_string());
+    let text = inner
+        .map(markdown_block)
+        .unwrap_or_else(|| "\n".to_string());
+
+    Ok(format!(
+        "\n\n<blockquote class=\"tweet-static\">{text}<footer>@{user} · <a href=\"{url}\">{link_text}</a></footer>\n</blockquote>\n\n",
+        text = text,
+        user = html_text(user),
+        url = url_attr(&url),
+        link_text = link_text,
+    ))
+}
+
+/// GETs `url` as text, or `None` on any failure
+fn fetch_text(url: &str) -> Option<String> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!("xeniria/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .ok()?;
+    let response = client.get(url).send().ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.text().ok()
+}
@@ -0,0 +1,107 @@
+//! schema.org JSON-LD blocks embedded in the `<head>` of generated pages.
+//!
+//! Everything here is derived from front matter and `SiteConfig`; string
+//! values go through `json_string` so they can't close the `<script>` tag.
+
+use crate::escape::json_string;
+use crate::markdown::Post;
+use crate::{absolute_url, SiteConfig};
+
+/// Wraps a JSON-LD object in its `<script>` tag.
+fn script(json: &str) -> String {
+    format!("<script type='application/ld+json'>{}</script>", json)
+}
+
+/// Whether a profile link actually names an account, i.e. it is not empty
+/// and has something after the host (`https://github.com/` alone does not).
+fn is_profile(link: &str) -> bool {
+    let link = link.trim();
+    let path = link.split_once("://").map_or(link, |(_, rest)| {
+        rest.split_once('/').map_or("", |(_, path)| path)
+    });
+    !path.trim_matches('/').is_empty()
+}
+
+/// `Person` object describing the site author.
+fn person(config: &SiteConfig) -> String {
+    let same_as = [&config.links.github, &config.links.twitter]
+        .into_iter()
+        .filter(|link| is_profile(link))
+        .map(|link| format!("\"{}\"", json_string(link.trim())))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{{\"@type\": \"Person\", \"name\": \"{name}\", \"url\": \"{url}\", \"image\": \"{image}\", \"sameAs\": [{same_as}]}}",
+        name = json_string(&config.site.author),
+        url = json_string(&absolute_url(&config.site.base_url, "/")),
+        image = json_string(&absolute_url(
+            &config.site.base_url,
+            &config.site.profile_picture
+        )),
+    )
+}
+
+/// `WebSite` and `Person` graph for the home page.
+pub fn website(config: &SiteConfig) -> String {
+    let json = format!(
+        "{{\"@context\": \"https://schema.org\", \"@graph\": [{{\"@type\": \"WebSite\", \"name\": \"{name}\", \"description\": \"{description}\", \"url\": \"{url}\", \"author\": {person}}}, {person}]}}",
+        name = json_string(&config.site.title),
+        description = json_string(&config.site.description),
+        url = json_string(&absolute_url(&config.site.base_url, "/")),
+        person = person(config),
+    );
+    script(&json)
+}
+
+/// `BlogPosting` for a single post.
+///
+/// `page_url` is the site-relative URL of the post and `image_url` the
+/// absolute URL of its preview image.
+pub fn blog_posting(post: &Post, page_url: &str, image_url: &str, config: &SiteConfig) -> String {
+    let front_matter = &post.front_matter;
+    let url = absolute_url(&config.site.base_url, page_url);
+    let description = front_matter
+        .description
+        .as_deref()
+        .unwrap_or(&config.site.description);
+
+    let json = format!(
+        "{{\"@context\": \"https://schema.org\", \"@type\": \"BlogPosting\", \"headline\": \"{headline}\", \"description\": \"{description}\", \"url\": \"{url}\", \"mainEntityOfPage\": \"{url}\", \"datePublished\": \"{published}\", \"dateModified\": \"{modified}\", \"author\": {{\"@type\": \"Person\", \"name\": \"{author}\"}}, \"publisher\": {publisher}, \"image\": \"{image}\", \"wordCount\": {word_count}}}",
+        headline = json_string(&front_matter.title),
+        description = json_string(description),
+        url = json_string(&url),
+        published = json_string(&front_matter.date),
+        modified = json_string(front_matter.updated.as_deref().unwrap_or(&front_matter.date)),
+        author = json_string(&front_matter.author),
+        publisher = person(config),
+        image = json_string(image_url),
+        word_count = post.word_count,
+    );
+    script(&json)
+}
+
+/// `BreadcrumbList` for a nested page.
+///
+/// `trail` lists `(name, site-relative URL)` pairs from the home page down to
+/// the current page.
+pub fn breadcrumbs(trail: &[(&str, &str)], config: &SiteConfig) -> String {
+    let items: Vec<String> = trail
+        .iter()
+        .enumerate()
+        .map(|(i, (name, path))| {
+            format!(
+                "{{\"@type\": \"ListItem\", \"position\": {position}, \"name\": \"{name}\", \"item\": \"{item}\"}}",
+                position = i + 1,
+                name = json_string(name),
+                item = json_string(&absolute_url(&config.site.base_url, path)),
+            )
+        })
+        .collect();
+
+    let json = format!(
+        "{{\"@context\": \"https://schema.org\", \"@type\": \"BreadcrumbList\", \"itemListElement\": [{}]}}",
+        items.join(", ")
+    );
+    script(&json)
+}